@@ -0,0 +1,94 @@
+use image::RgbaImage;
+
+fn map_rgb<F: Fn([f32; 3]) -> [f32; 3]>(img: &mut RgbaImage, f: F) {
+    for pixel in img.pixels_mut() {
+        let rgb = [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32];
+        let out = f(rgb);
+        for c in 0..3 {
+            pixel[c] = out[c].round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+fn luma([r, g, b]: [f32; 3]) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+pub fn brightness(img: &mut RgbaImage, percent: f32) {
+    let offset = percent / 100.0 * 255.0;
+    map_rgb(img, |rgb| rgb.map(|c| c + offset));
+}
+
+pub fn contrast(img: &mut RgbaImage, percent: f32) {
+    let factor = ((100.0 + percent) / 100.0).powi(2);
+    map_rgb(img, |rgb| rgb.map(|c| ((c / 255.0 - 0.5) * factor + 0.5) * 255.0));
+}
+
+pub fn saturation(img: &mut RgbaImage, percent: f32) {
+    let factor = 1.0 + percent / 100.0;
+    map_rgb(img, |rgb| {
+        let gray = luma(rgb);
+        rgb.map(|c| gray + (c - gray) * factor)
+    });
+}
+
+pub fn gamma(img: &mut RgbaImage, gamma: f32) {
+    let mut table = [0u8; 256];
+    for (i, v) in table.iter_mut().enumerate() {
+        *v = (255.0 * (i as f32 / 255.0).powf(1.0 / gamma)).round().clamp(0.0, 255.0) as u8;
+    }
+    for pixel in img.pixels_mut() {
+        for c in 0..3 {
+            pixel[c] = table[pixel[c] as usize];
+        }
+    }
+}
+
+pub fn grayscale(img: &mut RgbaImage) {
+    map_rgb(img, |rgb| [luma(rgb); 3]);
+}
+
+pub fn sepia(img: &mut RgbaImage) {
+    map_rgb(img, |[r, g, b]| [
+        0.393 * r + 0.769 * g + 0.189 * b,
+        0.349 * r + 0.686 * g + 0.168 * b,
+        0.272 * r + 0.534 * g + 0.131 * b,
+    ]);
+}
+
+pub fn invert(img: &mut RgbaImage) {
+    map_rgb(img, |rgb| rgb.map(|c| 255.0 - c));
+}
+
+pub fn auto_level(img: &mut RgbaImage) {
+    let mut histograms = [[0u32; 256]; 3];
+    let mut total = 0u32;
+    for pixel in img.pixels().filter(|p| p[3] > 0) {
+        for c in 0..3 {
+            histograms[c][pixel[c] as usize] += 1;
+        }
+        total += 1;
+    }
+    if total == 0 {
+        return;
+    }
+    let clip = total / 200;
+    let mut ranges = [(0.0f32, 255.0f32); 3];
+    for (c, histogram) in histograms.iter().enumerate() {
+        let mut seen = 0;
+        let low = histogram.iter().position(|&n| { seen += n; seen > clip }).unwrap_or(0);
+        seen = 0;
+        let high = 255 - histogram.iter().rev().position(|&n| { seen += n; seen > clip }).unwrap_or(0);
+        if high > low {
+            ranges[c] = (low as f32, high as f32);
+        }
+    }
+    map_rgb(img, |rgb| {
+        let mut out = rgb;
+        for c in 0..3 {
+            let (low, high) = ranges[c];
+            out[c] = (rgb[c] - low) / (high - low) * 255.0;
+        }
+        out
+    });
+}
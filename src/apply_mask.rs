@@ -1,10 +1,11 @@
 use image::io::Reader as ImageReader;
-use image::{RgbaImage, Rgba, DynamicImage, imageops};
+use image::{RgbaImage, Rgba, imageops};
 use ndarray::Array2;
 use colored::*;
+use crate::mask_refine::{self, AlphaMask, MaskOptions};
 
-pub fn apply_mask(original_path: &str, mask: Array2<f32>, output_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Applying mask to image: {} -> {}", original_path, output_path));
+pub fn apply_mask(original_path: &str, mask: Array2<f32>, options: &MaskOptions) -> Result<RgbaImage, Box<dyn std::error::Error>> {
+    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Applying mask to image: {}", original_path));
     let orig_img = ImageReader::open(original_path)
         .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to open original image '{}': {}", original_path, e)))?
         .decode()
@@ -13,25 +14,30 @@ pub fn apply_mask(original_path: &str, mask: Array2<f32>, output_path: &str) ->
     let (width, height) = orig_img.dimensions();
 
     let mask_img = {
-        let mut mask_buf = image::GrayImage::new(mask.shape()[1] as u32, mask.shape()[0] as u32);
+        let mut mask_buf = AlphaMask::new(mask.shape()[1] as u32, mask.shape()[0] as u32);
         for ((y, x), v) in mask.indexed_iter() {
-            let alpha = (v * 255.0).clamp(0.0, 255.0) as u8;
-            mask_buf.put_pixel(x as u32, y as u32, image::Luma([alpha]));
+            mask_buf.put_pixel(x as u32, y as u32, image::Luma([v.clamp(0.0, 1.0)]));
         }
-        DynamicImage::ImageLuma8(mask_buf)
+        mask_buf
     };
 
-    let resized_mask = mask_img.resize_exact(width, height, imageops::FilterType::Triangle).to_luma8();
+    let mut resized_mask = imageops::resize(&mask_img, width, height, imageops::FilterType::Triangle);
+    if options.refines_mask() {
+        println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), "Refining mask edges...");
+        resized_mask = mask_refine::refine(&orig_img, resized_mask, options);
+    }
+    let colors = if options.decontaminate {
+        println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), "Removing background color spill from edges...");
+        mask_refine::decontaminate(&orig_img, &resized_mask)
+    } else {
+        orig_img
+    };
 
     let mut output: RgbaImage = RgbaImage::new(width, height);
-    for (x, y, pixel) in orig_img.enumerate_pixels() {
+    for (x, y, pixel) in colors.enumerate_pixels() {
         let [r, g, b] = pixel.0;
-        let alpha = resized_mask.get_pixel(x, y)[0];
+        let alpha = (resized_mask.get_pixel(x, y)[0] * 255.0).round().clamp(0.0, 255.0) as u8;
         output.put_pixel(x, y, Rgba([r, g, b, alpha]));
     }
-
-    output.save(output_path)
-        .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to save masked image '{}': {}", output_path, e)))?;
-    println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("Mask applied and image saved: {}", output_path));
-    Ok(())
+    Ok(output)
 }
\ No newline at end of file
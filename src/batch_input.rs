@@ -0,0 +1,214 @@
+use colored::*;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+const INPUT_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct InputOptions {
+    #[arg(short = 'r', long, help = "Also process subdirectories and mirror their structure under the output directory")]
+    pub recursive: bool,
+    #[arg(long, requires = "recursive", help = "Follow symbolic links to directories while recursing")]
+    pub follow_symlinks: bool,
+    #[arg(long, value_name = "N", requires = "recursive", help = "How many levels of subdirectories to descend into (0 = top level only)")]
+    pub max_depth: Option<usize>,
+    #[arg(long, value_name = "GLOB", value_parser = parse_glob, help = "Only process files whose path relative to the input directory matches this glob, e.g. '**/*.png' (repeatable)")]
+    pub include: Vec<Glob>,
+    #[arg(long, value_name = "GLOB", value_parser = parse_glob, help = "Skip files whose path relative to the input directory matches this glob, e.g. '**/thumbs/**' (repeatable)")]
+    pub exclude: Vec<Glob>,
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, help = "Skip files smaller than this many bytes (K, M and G suffixes allowed)")]
+    pub min_size: Option<u64>,
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, help = "Skip files larger than this many bytes (K, M and G suffixes allowed)")]
+    pub max_size: Option<u64>,
+    #[arg(long, value_name = "PX", help = "Skip images narrower than this")]
+    pub min_width: Option<u32>,
+    #[arg(long, value_name = "PX", help = "Skip images shorter than this")]
+    pub min_height: Option<u32>,
+    #[arg(long, value_name = "DATE", value_parser = parse_date, help = "Only process files modified after this UTC date: YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS")]
+    pub newer_than: Option<SystemTime>,
+}
+
+/// An input file and its directory relative to the input root, which is recreated under the output directory.
+pub struct InputFile {
+    pub path: PathBuf,
+    pub relative_dir: PathBuf,
+}
+
+impl InputFile {
+    /// `<output_dir>/<relative_dir>/<stem>.<output_ext>`
+    pub fn output_path(&self, output_dir: &Path, output_ext: &str) -> PathBuf {
+        let stem = self.path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+        output_dir.join(&self.relative_dir).join(format!("{}.{}", stem, output_ext))
+    }
+}
+
+/// Globs are matched against `/`-separated relative paths, and `*` does not cross directories.
+fn parse_glob(value: &str) -> Result<Glob, String> {
+    GlobBuilder::new(value)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Byte count with an optional binary suffix: 500, 200K, 1.5M, 2G (a trailing B or iB is ignored).
+fn parse_size(value: &str) -> Result<u64, String> {
+    let lower = value.trim().to_lowercase();
+    let number = lower.trim_end_matches("ib").trim_end_matches('b');
+    let (number, multiplier) = match number.chars().last() {
+        Some('k') => (&number[..number.len() - 1], 1u64 << 10),
+        Some('m') => (&number[..number.len() - 1], 1 << 20),
+        Some('g') => (&number[..number.len() - 1], 1 << 30),
+        _ => (number, 1),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(n) if n >= 0.0 && n.is_finite() => Ok((n * multiplier as f64).round() as u64),
+        _ => Err(format!("'{}' is not a size (try 500K or 2M)", value)),
+    }
+}
+
+/// Days from 1970-01-01 to the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn parse_date(value: &str) -> Result<SystemTime, String> {
+    let invalid = || format!("'{}' is not a date (use YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS)", value);
+    let (date, time) = value.split_once(['T', ' ']).unwrap_or((value, "00:00:00"));
+    let date: Vec<&str> = date.split('-').collect();
+    let time: Vec<&str> = time.split(':').collect();
+    let [year, month, day] = date[..] else { return Err(invalid()) };
+    let (year, month, day): (i64, u32, u32) = match (year.parse(), month.parse(), day.parse()) {
+        (Ok(y), Ok(m), Ok(d)) if (1..=12).contains(&m) && (1..=31).contains(&d) => (y, m, d),
+        _ => return Err(invalid()),
+    };
+    let clock: Vec<u64> = time.iter().map(|part| part.parse().map_err(|_| invalid())).collect::<Result<_, _>>()?;
+    let (hours, minutes, seconds) = match clock[..] {
+        [h, m] => (h, m, 0),
+        [h, m, s] => (h, m, s),
+        _ => return Err(invalid()),
+    };
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return Err(invalid());
+    }
+    let seconds = days_from_civil(year, month, day) * 86400 + (hours * 3600 + minutes * 60 + seconds) as i64;
+    u64::try_from(seconds)
+        .map(|s| UNIX_EPOCH + Duration::from_secs(s))
+        .map_err(|_| format!("'{}' is before 1970", value))
+}
+
+fn build_set(globs: &[Glob]) -> Result<Option<GlobSet>, String> {
+    if globs.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(glob.clone());
+    }
+    builder.build().map(Some).map_err(|e| e.to_string())
+}
+
+fn is_supported(path: &Path) -> bool {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    INPUT_EXTENSIONS.contains(&ext.as_str())
+}
+
+impl InputOptions {
+    /// Size, date and dimension checks for a candidate file. Errors mean the file could not be inspected.
+    fn passes(&self, path: &Path) -> Result<bool, String> {
+        if self.min_size.is_some() || self.max_size.is_some() || self.newer_than.is_some() {
+            // fs::metadata follows links, so a linked file is judged by its target.
+            let metadata = fs::metadata(path).map_err(|e| format!("can't read metadata: {}", e))?;
+            if self.min_size.is_some_and(|min| metadata.len() < min) || self.max_size.is_some_and(|max| metadata.len() > max) {
+                return Ok(false);
+            }
+            if let Some(after) = self.newer_than {
+                let modified = metadata.modified().map_err(|e| format!("can't read modification time: {}", e))?;
+                if modified <= after {
+                    return Ok(false);
+                }
+            }
+        }
+        if self.min_width.is_some() || self.min_height.is_some() {
+            // Only the header is read here, not the whole image.
+            let (width, height) = image::image_dimensions(path).map_err(|e| format!("can't read dimensions: {}", e))?;
+            if self.min_width.is_some_and(|min| width < min) || self.min_height.is_some_and(|min| height < min) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Supported image files under `input_dir` that pass the filters, in a stable order. Anything inside
+    /// `output_dir` is skipped so that re-running with the output nested in the input does not pick up old results.
+    pub fn collect(&self, input_dir: &Path, output_dir: &Path) -> Result<Vec<InputFile>, String> {
+        if !input_dir.is_dir() {
+            return Err(format!("'{}' is not a directory", input_dir.display()));
+        }
+        let max_depth = if self.recursive { self.max_depth.map_or(usize::MAX, |depth| depth.saturating_add(1)) } else { 1 };
+        if let (Some(min), Some(max)) = (self.min_size, self.max_size) && min > max {
+            return Err(format!("--min-size ({}) is larger than --max-size ({})", min, max));
+        }
+        let include = build_set(&self.include)?;
+        let exclude = build_set(&self.exclude)?;
+        let output_dir = output_dir.canonicalize().unwrap_or_else(|_| output_dir.to_path_buf());
+        let mut files = vec![];
+        let mut filtered = 0;
+        let walker = WalkDir::new(input_dir)
+            .min_depth(1)
+            .max_depth(max_depth)
+            .follow_links(self.follow_symlinks)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| {
+                !entry.file_type().is_dir() || entry.path().canonicalize().map_or(true, |dir| dir != output_dir)
+            });
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) if e.depth() > 0 => {
+                    eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), format!("Skipping: {}", e));
+                    continue;
+                }
+                Err(e) => return Err(e.to_string()),
+            };
+            let path = entry.path();
+            // With symlinks not followed, a link to a file still counts as a file.
+            if !path.is_file() || !is_supported(path) {
+                continue;
+            }
+            let relative = path.strip_prefix(input_dir).unwrap_or(path);
+            let matched = include.as_ref().is_none_or(|set| set.is_match(relative))
+                && !exclude.as_ref().is_some_and(|set| set.is_match(relative));
+            if !matched {
+                filtered += 1;
+                continue;
+            }
+            match self.passes(path) {
+                Ok(true) => {}
+                Ok(false) => {
+                    filtered += 1;
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), format!("Skipping {}: {}", path.display(), e));
+                    continue;
+                }
+            }
+            let relative_dir = relative.parent().map(Path::to_path_buf).unwrap_or_default();
+            files.push(InputFile { path: path.to_path_buf(), relative_dir });
+        }
+        if filtered > 0 {
+            println!("{} {} {}", "[BATCH]".bold().cyan(), "→".cyan(), format!("{} files did not match the input filters", filtered));
+        }
+        Ok(files)
+    }
+}
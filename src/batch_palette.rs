@@ -0,0 +1,106 @@
+use colored::*;
+use palette::Lab;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use crate::palette_export::{self, PaletteExport};
+use crate::palette_extract::{self, PaletteColor, PaletteOptions};
+
+/// Upper bound on the samples each image adds to the collection palette, so a large
+/// shoot neither dominates by image size nor exhausts memory.
+const SAMPLES_PER_IMAGE: usize = 4096;
+
+#[derive(Clone, Debug)]
+pub struct BatchPalette {
+    pub options: PaletteOptions,
+    pub export: PaletteExport,
+    pub report: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ReportColor {
+    hex: String,
+    coverage_percent: f32,
+}
+
+#[derive(Serialize)]
+struct ImagePalette {
+    image: String,
+    output: String,
+    colors: Vec<ReportColor>,
+}
+
+#[derive(Serialize)]
+struct PaletteReport {
+    images: usize,
+    collection: Vec<ReportColor>,
+    per_image: Vec<ImagePalette>,
+}
+
+fn report_colors(palette: &[PaletteColor]) -> Vec<ReportColor> {
+    palette.iter()
+        .map(|c| ReportColor { hex: c.hex.clone(), coverage_percent: (c.share * 10000.0).round() / 100.0 })
+        .collect()
+}
+
+impl BatchPalette {
+    /// Samples every processed output, clusters the samples together and writes the report.
+    /// `processed` holds (input, output) paths.
+    pub fn run(&self, processed: &[(String, String)], output_dir: &Path) {
+        println!("{} {} {}", "[BATCH]".bold().cyan(), "→".cyan(), format!("Building the collection palette from {} images", processed.len()));
+        let mut collection: Vec<Lab> = vec![];
+        let mut per_image = vec![];
+        for (input_path, output_path) in processed {
+            // Same-format files without operations are not rewritten, so fall back to the input.
+            let source = if Path::new(output_path).is_file() { output_path } else { input_path };
+            let img = match image::open(source) {
+                Ok(img) => img,
+                Err(e) => {
+                    eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), format!("Skipping '{}' in the palette: {}", source, e));
+                    continue;
+                }
+            };
+            let samples = palette_extract::sample_pixels(&img);
+            let step = samples.len().div_ceil(SAMPLES_PER_IMAGE).max(1);
+            collection.extend(samples.iter().step_by(step));
+            per_image.push(ImagePalette {
+                image: input_path.clone(),
+                output: output_path.clone(),
+                colors: report_colors(&palette_extract::cluster_palette(&samples, &self.options)),
+            });
+        }
+        let palette = palette_extract::cluster_palette(&collection, &self.options);
+        if palette.is_empty() {
+            println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), "No opaque pixels found; no collection palette written.");
+            return;
+        }
+        palette_extract::print_palette(&palette);
+        if self.options.palette_contrast {
+            palette_extract::print_contrast_report(&palette, self.options.apca);
+        }
+
+        let report = PaletteReport { images: per_image.len(), collection: report_colors(&palette), per_image };
+        let report_path = match &self.report {
+            Some(path) => Path::new(path).to_path_buf(),
+            None => output_dir.join("palette-report.json"),
+        };
+        let written = serde_json::to_vec_pretty(&report)
+            .map_err(|e| e.to_string())
+            .and_then(|data| fs::write(&report_path, data).map_err(|e| e.to_string()));
+        match written {
+            Ok(()) => println!("{} {} {}", "[BATCH]".bold().green(), "✔".green(), format!("Palette report written to {}", report_path.display())),
+            Err(e) => eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), format!("Failed to write palette report '{}': {}", report_path.display(), e)),
+        }
+
+        let collection_name = output_dir.join("collection").display().to_string();
+        let exported = self.export.target(&collection_name).and_then(|target| match target {
+            Some((out, format)) => palette_export::write_palette(&palette, &out, format).map(|_| Some(out)),
+            None => Ok(None),
+        });
+        match exported {
+            Ok(Some(out)) => println!("{} {} {}", "[BATCH]".bold().green(), "✔".green(), format!("Collection palette written to {}", out.display())),
+            Ok(None) => {}
+            Err(e) => eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), e),
+        }
+    }
+}
@@ -1,14 +1,27 @@
 
 use colored::*;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fs;
-use std::path::{Path};
-use crate::{preprocess::preprocess_image, inference::run_inference, apply_mask::apply_mask};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
 use crate::png_to_jpg;
 use crate::jpg_to_png;
 use crate::webp_to_jpg;
 use crate::jpg_to_webp;
 use crate::png_to_webp;
 use crate::webp_to_png;
+use crate::to_gif;
+use crate::pipeline::{self, ImageOps};
+use crate::inference::{BackgroundRemover, MaskPredictor};
+use crate::models::ModelSpec;
+use crate::onnx_image::{ImageModelDescriptor, ImageModelRunner};
+use ndarray::{Array2, Array4};
+use crate::batch_palette::BatchPalette;
+use crate::placeholder::PlaceholderManifest;
+use crate::batch_input::{InputFile, InputOptions};
 
 pub struct BatchJob {
     pub input_dir: String,
@@ -16,78 +29,382 @@ pub struct BatchJob {
     pub format_index: usize,
     pub remove_bg: bool,
     pub strip_metadata: bool,
+    pub ops: ImageOps,
+    pub bg_batch_size: usize,
+    pub palette: Option<BatchPalette>,
+    pub placeholders: Option<PlaceholderManifest>,
+    pub jobs: usize,
+    pub input: InputOptions,
+}
+
+/// What a worker reports back for one input file; paths are (input, output).
+enum Outcome {
+    Done((String, String)),
+    Failed,
+}
+
+/// Input tensors from one worker and where to send the resulting masks.
+struct InferenceRequest {
+    tensors: Vec<Array4<f32>>,
+    reply: mpsc::Sender<Result<Vec<Array2<f32>>, String>>,
+}
+
+/// One --upscale/--colorize model run from a worker.
+struct EnhanceRequest {
+    descriptor: &'static ImageModelDescriptor,
+    input: Array4<f32>,
+    reply: mpsc::Sender<Result<Array4<f32>, String>>,
+}
+
+enum Message {
+    Finished(Outcome),
+    Infer(InferenceRequest),
+    Enhance(EnhanceRequest),
+    WorkerDone,
+}
+
+/// Tells the main thread a worker has stopped, even if it panicked, so it never waits on it.
+struct WorkerGuard(mpsc::Sender<Message>);
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        let _ = self.0.send(Message::WorkerDone);
+    }
+}
+
+/// A worker's stand-in for the ONNX session, which is not Send and stays on the main thread.
+/// Only the model run itself is serialized; loading, preprocessing and compositing happen
+/// on the worker.
+struct InferenceClient {
+    spec: &'static ModelSpec,
+    requests: mpsc::Sender<Message>,
+    reply: mpsc::Sender<Result<Vec<Array2<f32>>, String>>,
+    masks: mpsc::Receiver<Result<Vec<Array2<f32>>, String>>,
+}
+
+impl InferenceClient {
+    fn new(spec: &'static ModelSpec, requests: mpsc::Sender<Message>) -> Self {
+        let (reply, masks) = mpsc::channel();
+        InferenceClient { spec, requests, reply, masks }
+    }
+}
+
+impl MaskPredictor for InferenceClient {
+    fn spec(&self) -> &'static ModelSpec {
+        self.spec
+    }
+
+    fn run_batch(&mut self, inputs: Vec<Array4<f32>>) -> Result<Vec<Array2<f32>>, Box<dyn std::error::Error>> {
+        let request = InferenceRequest { tensors: inputs, reply: self.reply.clone() };
+        self.requests.send(Message::Infer(request)).map_err(|_| "Inference thread has stopped")?;
+        let masks = self.masks.recv().map_err(|_| "Inference thread has stopped")??;
+        Ok(masks)
+    }
+}
+
+/// Like `InferenceClient`, for the image models of --upscale and --colorize. Requests are not
+/// batched; the main thread runs each one as it arrives.
+struct EnhanceClient {
+    requests: mpsc::Sender<Message>,
+    reply: mpsc::Sender<Result<Array4<f32>, String>>,
+    outputs: mpsc::Receiver<Result<Array4<f32>, String>>,
+}
+
+impl EnhanceClient {
+    fn new(requests: mpsc::Sender<Message>) -> Self {
+        let (reply, outputs) = mpsc::channel();
+        EnhanceClient { requests, reply, outputs }
+    }
+}
+
+impl ImageModelRunner for EnhanceClient {
+    fn run(&mut self, descriptor: &'static ImageModelDescriptor, input: Array4<f32>) -> Result<Array4<f32>, String> {
+        let request = EnhanceRequest { descriptor, input, reply: self.reply.clone() };
+        self.requests.send(Message::Enhance(request)).map_err(|_| "Inference thread has stopped".to_string())?;
+        self.outputs.recv().map_err(|_| "Inference thread has stopped".to_string())?
+    }
+}
+
+/// Runs the queued requests through the session, at most `batch_size` tensors per run,
+/// and hands each worker its own masks back.
+fn serve(remover: &mut impl MaskPredictor, waiting: &mut Vec<InferenceRequest>, batch_size: usize) {
+    let requests = std::mem::take(waiting);
+    let counts: Vec<usize> = requests.iter().map(|r| r.tensors.len()).collect();
+    let mut tensors = vec![];
+    let mut replies = vec![];
+    for request in requests {
+        tensors.extend(request.tensors);
+        replies.push(request.reply);
+    }
+    let mut masks = vec![];
+    let mut remaining = tensors.into_iter();
+    loop {
+        let chunk: Vec<Array4<f32>> = remaining.by_ref().take(batch_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        match remover.run_batch(chunk) {
+            Ok(chunk_masks) => masks.extend(chunk_masks),
+            Err(e) => {
+                for reply in replies {
+                    let _ = reply.send(Err(e.to_string()));
+                }
+                return;
+            }
+        }
+    }
+    let mut masks = masks.into_iter();
+    for (reply, count) in replies.into_iter().zip(counts) {
+        // A worker that has gone away no longer needs its masks.
+        let _ = reply.send(Ok(masks.by_ref().take(count).collect()));
+    }
+}
+
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
 }
 
 impl BatchJob {
     pub fn run(&self) {
-        let formats = ["jpg", "png", "webp"];
+        let formats = ["jpg", "png", "webp", "gif"];
         let output_ext = formats[self.format_index];
         let input_dir = Path::new(&self.input_dir);
         let output_dir = Path::new(&self.output_dir);
-        let mut count = 0;
-        let mut errors = 0;
-        if !output_dir.exists() {
-            if let Err(e) = fs::create_dir_all(output_dir) {
-                eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to create output dir: {}", e));
-                return;
+        let mut processed: Vec<(String, String)> = vec![];
+        let mut errors: usize = 0;
+        if !output_dir.exists() && let Err(e) = fs::create_dir_all(output_dir) {
+            eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to create output dir: {}", e));
+            return;
+        }
+        if self.remove_bg && let Err(e) = self.ops.export.prepare_batch() {
+            eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), e);
+            return;
+        }
+        let bg_supported = output_ext == "png" || output_ext == "webp" || output_ext == "gif" || !self.ops.needs_alpha_output();
+        let mut remover = None;
+        if bg_supported && self.remove_bg {
+            match BackgroundRemover::from_options(&self.ops.model, &self.ops.onnx) {
+                Ok(r) => remover = Some(r),
+                Err(e) => {
+                    eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), format!("Background removal unavailable: {}", e));
+                    return;
+                }
             }
         }
-        let entries = match fs::read_dir(input_dir) {
-            Ok(e) => e,
+        let mut image_models = match self.ops.enhance.load(&self.ops.onnx) {
+            Ok(models) => models,
             Err(e) => {
-                eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to read input dir: {}", e));
+                eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), format!("Image enhancement unavailable: {}", e));
                 return;
             }
         };
-        for entry in entries.filter_map(|e| e.ok()) {
-            let path = entry.path();
-            if !path.is_file() { continue; }
-            let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
-            let ext = if ext == "jpeg" { "jpg".to_string() } else { ext };
-            if !["jpg", "png", "webp"].contains(&ext.as_str()) { continue; }
-            let file_stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
-            let out_path = output_dir.join(format!("{}.{}", file_stem, output_ext));
-            let input_path = path.display().to_string();
-            let output_path = out_path.display().to_string();
-            println!("{} {} Processing: {} -> {}", "[BATCH]".bold().cyan(), "→".cyan(), input_path, output_path);
-            let result = if ext == output_ext {
-                Ok(()) 
-            } else {
-                match ((ext.as_str(), output_ext), (ext == "jpg", output_ext == "jpg")) {
-                    (("png", out), (_, true)) if out == "jpg" => png_to_jpg::png_to_jpg(&input_path, &output_path),
-                    ((inp, "png"), (true, _)) if inp == "jpg" => jpg_to_png::jpg_to_png(&input_path, &output_path),
-                    (("webp", out), (_, true)) if out == "jpg" => webp_to_jpg::webp_to_jpg(&input_path, &output_path),
-                    ((inp, "webp"), (true, _)) if inp == "jpg" => jpg_to_webp::jpg_to_webp(&input_path, &output_path),
-                    (("png", "webp"), _) => png_to_webp::png_to_webp(&input_path, &output_path),
-                    (("webp", "png"), _) => webp_to_png::webp_to_png(&input_path, &output_path),
-                    _ => Err(format!("[ERROR] Conversion from {} to {} is not supported.", ext, output_ext)),
+        let bg_batch_size = match &remover {
+            Some(r) if r.supports_batching() => self.bg_batch_size.max(1),
+            _ => 1,
+        };
+        let files = match self.input.collect(input_dir, output_dir) {
+            Ok(files) => files,
+            Err(e) => {
+                eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to collect input files: {}", e));
+                return;
+            }
+        };
+        // Files that differ only in extension (photo.jpg, photo.png) would write the same output.
+        // The first one in sorted order keeps it, the others are reported and skipped.
+        let mut claimed: HashMap<PathBuf, PathBuf> = HashMap::new();
+        let files: Vec<InputFile> = files.into_iter().filter(|file| {
+            match claimed.entry(file.output_path(output_dir, output_ext)) {
+                Entry::Occupied(first) => {
+                    eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), format!("Skipping {}: {} is already the output of {}", file.path.display(), first.key().display(), first.get().display()));
+                    errors += 1;
+                    false
                 }
-            };
-            match result {
-                Ok(_) => {
-                    if (output_ext == "png" || output_ext == "webp") && self.remove_bg {
-                        match preprocess_image(&input_path)
-                            .and_then(|input_tensor| run_inference(input_tensor))
-                            .and_then(|mask| apply_mask(&input_path, mask, &output_path)) {
-                            Ok(_) => println!("{} {} {}", "[BATCH]".bold().green(), "✔".green(), format!("Background removed: {}", output_path)),
-                            Err(e) => {
-                                eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), format!("BG removal failed: {}", e));
-                                errors += 1;
-                                continue;
-                            }
+                Entry::Vacant(slot) => {
+                    slot.insert(file.path.clone());
+                    true
+                }
+            }
+        }).collect();
+        let jobs = self.jobs.clamp(1, files.len().max(1));
+        println!("{} {} {}", "[BATCH]".bold().cyan(), "→".cyan(), format!("Processing {} files with {} worker(s)", files.len(), jobs));
+
+        // Workers convert files in parallel. ONNX sessions are not Send, so the sessions stay on
+        // this thread and workers send their input tensors over and wait for the results.
+        let next = AtomicUsize::new(0);
+        let spec = remover.as_ref().map(|r| r.model.spec);
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for _ in 0..jobs {
+                let sender = sender.clone();
+                let (files, next) = (&files, &next);
+                scope.spawn(move || {
+                    let _guard = WorkerGuard(sender.clone());
+                    let mut client = spec.map(|spec| InferenceClient::new(spec, sender.clone()));
+                    let mut enhance = EnhanceClient::new(sender.clone());
+                    while let Some(file) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let outcome = self.convert_file(file, output_dir, output_ext, client.as_mut(), &mut enhance);
+                        if sender.send(Message::Finished(outcome)).is_err() {
+                            break;
                         }
                     }
-                    if self.strip_metadata {
-                        println!("{} {} {}", "[BATCH]".bold().yellow(), "ℹ".yellow(), format!("[TODO] Strip metadata: {}", output_path));
+                });
+            }
+            drop(sender);
+            let mut waiting: Vec<InferenceRequest> = vec![];
+            let mut running = jobs;
+            for message in receiver {
+                match message {
+                    Message::Finished(Outcome::Done(paths)) => processed.push(paths),
+                    Message::Finished(Outcome::Failed) => errors += 1,
+                    Message::Infer(request) => waiting.push(request),
+                    Message::Enhance(request) => {
+                        let _ = request.reply.send(image_models.run(request.descriptor, request.input));
                     }
-                    count += 1;
+                    Message::WorkerDone => running -= 1,
                 }
-                Err(e) => {
-                    eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), e);
-                    errors += 1;
+                // Each running worker has at most one request out, so once all of them are
+                // waiting (or the batch is full) nothing is gained by holding on.
+                let queued: usize = waiting.iter().map(|r| r.tensors.len()).sum();
+                if let Some(remover) = remover.as_mut() && !waiting.is_empty() && (queued >= bg_batch_size || waiting.len() >= running) {
+                    serve(remover, &mut waiting, bg_batch_size);
+                }
+            }
+        });
+        if let Some(remover) = &remover {
+            remover.print_benchmark_summary();
+        }
+        // Workers finish in any order; sort so the reports and the palette clustering are reproducible.
+        processed.sort();
+        if let Some(palette) = &self.palette {
+            palette.run(&processed, output_dir);
+        }
+        if let Some(placeholders) = &self.placeholders {
+            placeholders.run(&processed, output_dir);
+        }
+        println!("\n{} {} Batch complete. {} files processed, {} errors.", "[BATCH]".bold().green(), "✔".green(), processed.len(), errors);
+    }
+
+    /// Converts one file and applies the operations, with background removal when a session is
+    /// available. Every log line is a single println!/eprintln! call, so lines from different
+    /// workers never mix.
+    fn convert_file(&self, file: &InputFile, output_dir: &Path, output_ext: &str, remover: Option<&mut InferenceClient>, models: &mut EnhanceClient) -> Outcome {
+        let path = file.path.as_path();
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        let ext = if ext == "jpeg" { "jpg".to_string() } else { ext };
+        let out_path = file.output_path(output_dir, output_ext);
+        let target_dir = output_dir.join(&file.relative_dir);
+        if let Err(e) = fs::create_dir_all(&target_dir) {
+            eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), format!("Failed to create output dir '{}': {}", target_dir.display(), e));
+            return Outcome::Failed;
+        }
+        let input_path = path.display().to_string();
+        let output_path = out_path.display().to_string();
+        println!("{} {} Processing: {} -> {}", "[BATCH]".bold().cyan(), "→".cyan(), input_path, output_path);
+        if let Some(remover) = remover {
+            // Background removal reads the input and writes the output itself.
+            return self.remove_background(input_path, output_path, &file.relative_dir, output_ext, remover, models);
+        }
+        let result = if ext == output_ext {
+            Ok(())
+        } else {
+            match ((ext.as_str(), output_ext), (ext == "jpg", output_ext == "jpg")) {
+                (("png", out), (_, true)) if out == "jpg" => png_to_jpg::png_to_jpg(&input_path, &output_path),
+                ((inp, "png"), (true, _)) if inp == "jpg" => jpg_to_png::jpg_to_png(&input_path, &output_path),
+                (("webp", out), (_, true)) if out == "jpg" => webp_to_jpg::webp_to_jpg(&input_path, &output_path),
+                ((inp, "webp"), (true, _)) if inp == "jpg" => jpg_to_webp::jpg_to_webp(&input_path, &output_path),
+                (("png", "webp"), _) => png_to_webp::png_to_webp(&input_path, &output_path),
+                (("webp", "png"), _) => webp_to_png::webp_to_png(&input_path, &output_path),
+                ((_, "gif"), _) => to_gif::to_gif(&input_path, &output_path),
+                _ => Err(format!("[ERROR] Conversion from {} to {} is not supported.", ext, output_ext)),
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), e);
+            return Outcome::Failed;
+        }
+        if !self.ops.is_empty() && let Err(e) = pipeline::apply_operations(&input_path, &output_path, output_ext, &self.ops, models) {
+            eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), e);
+            return Outcome::Failed;
+        }
+        if self.strip_metadata {
+            println!("{} {} {}", "[BATCH]".bold().yellow(), "ℹ".yellow(), format!("[TODO] Strip metadata: {}", output_path));
+        }
+        Outcome::Done((input_path, output_path))
+    }
+
+    fn remove_background(&self, input_path: String, output_path: String, relative_dir: &Path, output_ext: &str, remover: &mut InferenceClient, models: &mut EnhanceClient) -> Outcome {
+        match pipeline::remove_background(&input_path, &output_path, relative_dir, output_ext, &self.ops, remover, models) {
+            Ok(()) => {
+                println!("{} {} {}", "[BATCH]".bold().green(), "✔".green(), format!("Background removed: {}", output_path));
+                if self.strip_metadata {
+                    println!("{} {} {}", "[BATCH]".bold().yellow(), "ℹ".yellow(), format!("[TODO] Strip metadata: {}", output_path));
                 }
+                Outcome::Done((input_path, output_path))
+            }
+            Err(e) => {
+                eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), format!("BG removal failed: {}", e));
+                Outcome::Failed
             }
         }
-        println!("\n{} {} Batch complete. {} files processed, {} errors.", "[BATCH]".bold().green(), "✔".green(), count, errors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ModelKind;
+
+    /// Answers every input with a 1x1 mask holding the input's first value, counting the runs.
+    struct Echo {
+        runs: Vec<usize>,
+    }
+
+    impl MaskPredictor for Echo {
+        fn spec(&self) -> &'static ModelSpec {
+            ModelKind::U2net.spec()
+        }
+
+        fn run_batch(&mut self, inputs: Vec<Array4<f32>>) -> Result<Vec<Array2<f32>>, Box<dyn std::error::Error>> {
+            self.runs.push(inputs.len());
+            Ok(inputs.iter().map(|input| Array2::from_elem((1, 1), input[[0, 0, 0, 0]])).collect())
+        }
+    }
+
+    fn tensor(value: f32) -> Array4<f32> {
+        Array4::from_elem((1, 3, 1, 1), value)
+    }
+
+    fn values(masks: Result<Vec<Array2<f32>>, String>) -> Vec<f32> {
+        masks.unwrap().iter().map(|mask| mask[[0, 0]]).collect()
+    }
+
+    #[test]
+    fn serve_splits_runs_by_batch_size_and_returns_masks_to_their_requests() {
+        let (first, first_masks) = mpsc::channel();
+        let (second, second_masks) = mpsc::channel();
+        let mut waiting = vec![
+            InferenceRequest { tensors: vec![tensor(1.0)], reply: first },
+            InferenceRequest { tensors: vec![tensor(2.0), tensor(3.0), tensor(4.0)], reply: second },
+        ];
+        let mut echo = Echo { runs: vec![] };
+        serve(&mut echo, &mut waiting, 3);
+        assert!(waiting.is_empty());
+        assert_eq!(echo.runs, vec![3, 1]);
+        assert_eq!(values(first_masks.recv().unwrap()), vec![1.0]);
+        assert_eq!(values(second_masks.recv().unwrap()), vec![2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn client_waits_for_the_serving_thread() {
+        let (sender, receiver) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            let mut client = InferenceClient::new(ModelKind::U2net.spec(), sender);
+            client.run(tensor(5.0)).map(|mask| mask[[0, 0]]).map_err(|e| e.to_string())
+        });
+        let Ok(Message::Infer(request)) = receiver.recv() else {
+            panic!("expected an inference request");
+        };
+        serve(&mut Echo { runs: vec![] }, &mut vec![request], 4);
+        assert_eq!(worker.join().unwrap(), Ok(5.0));
     }
 }
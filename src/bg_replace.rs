@@ -0,0 +1,53 @@
+use image::{imageops, Rgba, RgbaImage};
+use colored::*;
+use std::path::Path;
+use crate::pipeline::parse_hex_color;
+
+#[derive(Clone, Debug)]
+pub enum Background {
+    Color(Rgba<u8>),
+    Image(String),
+}
+
+pub fn parse_background(value: &str) -> Result<Background, String> {
+    if value.starts_with('#') {
+        parse_hex_color(value).map(Background::Color)
+    } else if Path::new(value).is_file() {
+        Ok(Background::Image(value.to_string()))
+    } else {
+        Err(format!("'{}' is neither a hex color (#RRGGBB) nor an existing image file", value))
+    }
+}
+
+fn composite_over(cutout: &RgbaImage, mut background: RgbaImage) -> RgbaImage {
+    imageops::overlay(&mut background, cutout, 0, 0);
+    background
+}
+
+pub fn replace_background(cutout: &RgbaImage, background: &Background) -> Result<RgbaImage, String> {
+    let (width, height) = cutout.dimensions();
+    let background = match background {
+        Background::Color(color) => {
+            println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Replacing background with #{:02X}{:02X}{:02X}", color[0], color[1], color[2]));
+            RgbaImage::from_pixel(width, height, *color)
+        }
+        Background::Image(path) => {
+            println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Replacing background with image: {}", path));
+            image::open(path)
+                .map_err(|e| format!("[ERROR] Failed to open background image '{}': {}", path, e))?
+                .resize_to_fill(width, height, imageops::FilterType::Lanczos3)
+                .to_rgba8()
+        }
+    };
+    Ok(composite_over(cutout, background))
+}
+
+pub fn blur_background(cutout: &RgbaImage, sigma: f32) -> RgbaImage {
+    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Blurring background (sigma {})", sigma));
+    let mut original = cutout.clone();
+    for pixel in original.pixels_mut() {
+        pixel[3] = 255;
+    }
+    let background = imageops::blur(&original, sigma);
+    composite_over(cutout, background)
+}
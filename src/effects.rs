@@ -0,0 +1,186 @@
+use image::{imageops, GrayImage, Luma, Rgba, RgbaImage};
+use colored::*;
+use crate::mask_export;
+use crate::pipeline::parse_hex_color;
+use crate::watermark;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Offset {
+    pub x: i32,
+    pub y: i32,
+}
+
+pub fn parse_offset(value: &str) -> Result<Offset, String> {
+    let (x, y) = value.split_once(',')
+        .ok_or_else(|| format!("'{}' must be X,Y, e.g. 8,8", value))?;
+    let x = x.trim().parse().map_err(|_| format!("Invalid x offset '{}'", x))?;
+    let y = y.trim().parse().map_err(|_| format!("Invalid y offset '{}'", y))?;
+    Ok(Offset { x, y })
+}
+
+/// Like `filters::parse_sigma`, but 0 is allowed and gives a hard-edged shadow.
+pub fn parse_shadow_blur(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(sigma) if sigma.is_finite() && (0.0..=100.0).contains(&sigma) => Ok(sigma),
+        _ => Err(format!("'{}' is not a valid shadow blur (must be between 0 and 100)", value)),
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct CutoutEffects {
+    #[arg(long, requires = "remove_bg", conflicts_with_all = ["mask_only", "bg_blur"], help = "Add a soft drop shadow under the cut-out subject")]
+    pub shadow: bool,
+    #[arg(long, value_name = "X,Y", default_value = "8,8", value_parser = parse_offset, allow_hyphen_values = true, requires = "shadow", help = "Shadow offset in pixels")]
+    pub shadow_offset: Offset,
+    #[arg(long, value_name = "SIGMA", default_value_t = 8.0, value_parser = parse_shadow_blur, requires = "shadow", help = "Shadow blur sigma from 0 (hard edge) to 100")]
+    pub shadow_blur: f32,
+    #[arg(long, value_name = "0-1", default_value_t = 0.5, value_parser = watermark::parse_opacity, requires = "shadow", help = "Shadow opacity from 0 (invisible) to 1 (opaque)")]
+    pub shadow_opacity: f32,
+    #[arg(long, value_name = "HEX", default_value = "#000000", value_parser = parse_hex_color, requires = "shadow", help = "Shadow color as #RRGGBB")]
+    pub shadow_color: Rgba<u8>,
+    #[arg(long, value_name = "PX", requires = "remove_bg", conflicts_with_all = ["mask_only", "bg_blur"], help = "Draw a sticker outline of this width around the cut-out subject")]
+    pub outline: Option<u32>,
+    #[arg(long, value_name = "HEX", default_value = "#FFFFFF", value_parser = parse_hex_color, requires = "outline", help = "Outline color as #RRGGBB")]
+    pub outline_color: Rgba<u8>,
+}
+
+impl Default for CutoutEffects {
+    fn default() -> Self {
+        CutoutEffects {
+            shadow: false,
+            shadow_offset: Offset { x: 8, y: 8 },
+            shadow_blur: 8.0,
+            shadow_opacity: 0.5,
+            shadow_color: Rgba([0, 0, 0, 255]),
+            outline: None,
+            outline_color: Rgba([255, 255, 255, 255]),
+        }
+    }
+}
+
+/// One-dimensional squared distance transform (Felzenszwalb & Huttenlocher).
+fn distance_1d(f: &[f64]) -> Vec<f64> {
+    let n = f.len();
+    if n == 0 {
+        return vec![];
+    }
+    let mut d = vec![0.0f64; n];
+    let mut v = vec![0usize; n];
+    let mut z = vec![0.0f64; n + 1];
+    let mut k = 0;
+    z[0] = f64::NEG_INFINITY;
+    z[1] = f64::INFINITY;
+    for q in 1..n {
+        loop {
+            let p = v[k];
+            let s = ((f[q] + (q * q) as f64) - (f[p] + (p * p) as f64)) / (2.0 * (q as f64 - p as f64));
+            if s <= z[k] {
+                k -= 1;
+                continue;
+            }
+            k += 1;
+            v[k] = q;
+            z[k] = s;
+            z[k + 1] = f64::INFINITY;
+            break;
+        }
+    }
+    k = 0;
+    for (q, out) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+        let p = v[k];
+        *out = (q as f64 - p as f64).powi(2) + f[p];
+    }
+    d
+}
+
+/// Euclidean distance from every pixel to the nearest mostly opaque pixel.
+fn distance_to_subject(alpha: &GrayImage) -> Vec<f32> {
+    let (width, height) = (alpha.width() as usize, alpha.height() as usize);
+    let far = ((width + height) * (width + height)) as f64;
+    let mut grid: Vec<f64> = alpha.pixels().map(|p| if p[0] >= 128 { 0.0 } else { far }).collect();
+    for x in 0..width {
+        let column: Vec<f64> = (0..height).map(|y| grid[y * width + x]).collect();
+        for (y, value) in distance_1d(&column).into_iter().enumerate() {
+            grid[y * width + x] = value;
+        }
+    }
+    for row in grid.chunks_mut(width) {
+        let distances = distance_1d(row);
+        row.copy_from_slice(&distances);
+    }
+    grid.into_iter().map(|d| d.sqrt() as f32).collect()
+}
+
+fn colored_layer(alpha: &GrayImage, color: Rgba<u8>, opacity: f32) -> RgbaImage {
+    RgbaImage::from_fn(alpha.width(), alpha.height(), |x, y| {
+        let a = (alpha.get_pixel(x, y)[0] as f32 * opacity).round().clamp(0.0, 255.0) as u8;
+        Rgba([color[0], color[1], color[2], a])
+    })
+}
+
+impl CutoutEffects {
+    pub fn is_enabled(&self) -> bool {
+        self.shadow || self.outline.is_some()
+    }
+
+    /// Grows the canvas just enough that the outline and shadow are not clipped.
+    fn padded(&self, cutout: RgbaImage) -> RgbaImage {
+        let Some(bbox) = mask_export::subject_bbox(&mask_export::alpha_channel(&cutout)) else {
+            return cutout;
+        };
+        let grow = self.outline.unwrap_or(0) as i64;
+        let mut x0 = bbox.x as i64 - grow;
+        let mut y0 = bbox.y as i64 - grow;
+        let mut x1 = (bbox.x + bbox.width) as i64 + grow;
+        let mut y1 = (bbox.y + bbox.height) as i64 + grow;
+        if self.shadow {
+            let spread = (self.shadow_blur * 3.0).ceil() as i64;
+            let (dx, dy) = (self.shadow_offset.x as i64, self.shadow_offset.y as i64);
+            x0 = x0.min(x0 + dx - spread);
+            y0 = y0.min(y0 + dy - spread);
+            x1 = x1.max(x1 + dx + spread);
+            y1 = y1.max(y1 + dy + spread);
+        }
+        let (width, height) = (cutout.width() as i64, cutout.height() as i64);
+        let (left, top) = ((-x0).max(0), (-y0).max(0));
+        let (right, bottom) = ((x1 - width).max(0), (y1 - height).max(0));
+        if left + top + right + bottom == 0 {
+            return cutout;
+        }
+        let mut canvas = RgbaImage::new((width + left + right) as u32, (height + top + bottom) as u32);
+        imageops::replace(&mut canvas, &cutout, left, top);
+        canvas
+    }
+
+    pub fn apply(&self, cutout: RgbaImage) -> RgbaImage {
+        if !self.is_enabled() {
+            return cutout;
+        }
+        let mut canvas = self.padded(cutout);
+        if let Some(width) = self.outline {
+            println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Adding a {} px sticker outline", width));
+            let distances = distance_to_subject(&mask_export::alpha_channel(&canvas));
+            let outline_alpha = GrayImage::from_fn(canvas.width(), canvas.height(), |x, y| {
+                let distance = distances[(y * canvas.width() + x) as usize];
+                Luma([((width as f32 + 0.5 - distance).clamp(0.0, 1.0) * 255.0).round() as u8])
+            });
+            let mut layer = colored_layer(&outline_alpha, self.outline_color, 1.0);
+            imageops::overlay(&mut layer, &canvas, 0, 0);
+            canvas = layer;
+        }
+        if self.shadow {
+            println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), "Adding a drop shadow");
+            let alpha = mask_export::alpha_channel(&canvas);
+            let shadow_alpha = if self.shadow_blur > 0.0 { imageops::blur(&alpha, self.shadow_blur) } else { alpha };
+            let shadow = colored_layer(&shadow_alpha, self.shadow_color, self.shadow_opacity);
+            let mut layer = RgbaImage::new(canvas.width(), canvas.height());
+            imageops::overlay(&mut layer, &shadow, self.shadow_offset.x as i64, self.shadow_offset.y as i64);
+            imageops::overlay(&mut layer, &canvas, 0, 0);
+            canvas = layer;
+        }
+        canvas
+    }
+}
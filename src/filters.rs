@@ -0,0 +1,128 @@
+use image::{imageops, RgbaImage};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UnsharpMask {
+    pub radius: f32,
+    pub amount: f32,
+    pub threshold: u8,
+}
+
+pub fn parse_unsharp(value: &str) -> Result<UnsharpMask, String> {
+    let parts: Vec<&str> = value.split(',').map(|p| p.trim()).collect();
+    if parts.is_empty() || parts.len() > 3 {
+        return Err(format!("'{}' must be radius[,amount[,threshold]], e.g. 1.5,0.8,3", value));
+    }
+    let radius: f32 = parts[0].parse()
+        .map_err(|_| format!("Invalid sharpen radius '{}'", parts[0]))?;
+    let amount: f32 = match parts.get(1) {
+        Some(p) => p.parse().map_err(|_| format!("Invalid sharpen amount '{}'", p))?,
+        None => 1.0,
+    };
+    let threshold: u8 = match parts.get(2) {
+        Some(p) => p.parse().map_err(|_| format!("Invalid sharpen threshold '{}' (0-255)", p))?,
+        None => 0,
+    };
+    if !radius.is_finite() || radius <= 0.0 || !amount.is_finite() || amount < 0.0 {
+        return Err(format!("'{}' needs a radius above 0 and a non-negative amount", value));
+    }
+    Ok(UnsharpMask { radius, amount, threshold })
+}
+
+/// Blur strength. `imageops::blur` silently turns a sigma of 0 or less into 1.0, so refuse it here.
+pub fn parse_sigma(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(sigma) if sigma.is_finite() && sigma > 0.0 => Ok(sigma),
+        _ => Err(format!("'{}' is not a valid blur sigma (must be a number above 0)", value)),
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Debug)]
+#[clap(rename_all = "lower")]
+pub enum Denoise {
+    Median,
+    Bilateral,
+}
+
+pub fn blur(img: &RgbaImage, sigma: f32) -> RgbaImage {
+    imageops::blur(img, sigma)
+}
+
+pub fn sharpen(img: &RgbaImage, mask: UnsharpMask) -> RgbaImage {
+    let blurred = imageops::blur(img, mask.radius);
+    let mut out = img.clone();
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let soft = blurred.get_pixel(x, y);
+        for c in 0..3 {
+            let diff = pixel[c] as f32 - soft[c] as f32;
+            if diff.abs() >= mask.threshold as f32 {
+                pixel[c] = (pixel[c] as f32 + mask.amount * diff).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+    out
+}
+
+fn window(width: u32, height: u32, x: u32, y: u32, radius: u32) -> impl Iterator<Item = (u32, u32)> {
+    let x0 = x.saturating_sub(radius);
+    let y0 = y.saturating_sub(radius);
+    let x1 = x.saturating_add(radius).min(width - 1);
+    let y1 = y.saturating_add(radius).min(height - 1);
+    (y0..=y1).flat_map(move |ny| (x0..=x1).map(move |nx| (nx, ny)))
+}
+
+fn median(img: &RgbaImage, radius: u32) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let mut out = img.clone();
+    let mut values: [Vec<u8>; 3] = Default::default();
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        for v in values.iter_mut() {
+            v.clear();
+        }
+        for (nx, ny) in window(width, height, x, y, radius) {
+            let n = img.get_pixel(nx, ny);
+            for c in 0..3 {
+                values[c].push(n[c]);
+            }
+        }
+        for c in 0..3 {
+            let mid = values[c].len() / 2;
+            pixel[c] = *values[c].select_nth_unstable(mid).1;
+        }
+    }
+    out
+}
+
+fn bilateral(img: &RgbaImage, radius: u32) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let sigma_space = radius.max(1) as f32;
+    let sigma_color = 25.0f32;
+    let mut out = img.clone();
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let center = img.get_pixel(x, y);
+        let mut sum = [0.0f32; 3];
+        let mut weight_sum = 0.0f32;
+        for (nx, ny) in window(width, height, x, y, radius) {
+            let n = img.get_pixel(nx, ny);
+            let dx = nx as f32 - x as f32;
+            let dy = ny as f32 - y as f32;
+            let color_dist: f32 = (0..3).map(|c| (n[c] as f32 - center[c] as f32).powi(2)).sum();
+            let weight = (-(dx * dx + dy * dy) / (2.0 * sigma_space * sigma_space)
+                - color_dist / (2.0 * sigma_color * sigma_color)).exp();
+            for c in 0..3 {
+                sum[c] += n[c] as f32 * weight;
+            }
+            weight_sum += weight;
+        }
+        for c in 0..3 {
+            pixel[c] = (sum[c] / weight_sum).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    out
+}
+
+pub fn denoise(img: &RgbaImage, method: Denoise, radius: u32) -> RgbaImage {
+    match method {
+        Denoise::Median => median(img, radius),
+        Denoise::Bilateral => bilateral(img, radius),
+    }
+}
@@ -1,32 +1,252 @@
-use onnxruntime::{environment::Environment, GraphOptimizationLevel, tensor::OrtOwnedTensor};
-use ndarray::Array4;
-use std::path::Path;
+use onnxruntime::{environment::Environment, session::Session, GraphOptimizationLevel, tensor::OrtOwnedTensor};
+use ndarray::{Array2, Array4, Axis};
 use colored::*;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use crate::models::{self, ModelOptions, ModelSpec, OutputSpec, ResolvedModel};
 
-pub fn run_inference(input: Array4<f32>) -> Result<ndarray::Array2<f32>, Box<dyn std::error::Error>> {
-    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), "Loading ONNX model from models/u2net.onnx");
-    let environment = Environment::builder().with_name("u2net").build()
+static ENVIRONMENT: OnceLock<Environment> = OnceLock::new();
+
+fn postprocess(mut mask: Array2<f32>, output: &OutputSpec) -> Array2<f32> {
+    if output.sigmoid {
+        mask.mapv_inplace(|v| 1.0 / (1.0 + (-v).exp()));
+    }
+    if output.min_max {
+        let min = mask.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = mask.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        if max - min > f32::EPSILON {
+            mask.mapv_inplace(|v| (v - min) / (max - min));
+        }
+    }
+    mask
+}
+
+fn environment() -> Result<&'static Environment, String> {
+    if let Some(environment) = ENVIRONMENT.get() {
+        return Ok(environment);
+    }
+    let environment = Environment::builder().with_name("image_converter").build()
         .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to create ONNX environment: {}", e)))?;
-    let model_path = Path::new("models/u2net.onnx");
-    if !model_path.exists() {
-        return Err(format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Model file not found at {:?}. Please ensure models/u2net.onnx exists.", model_path)).into());
+    Ok(ENVIRONMENT.get_or_init(|| environment))
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Debug)]
+#[clap(rename_all = "lower")]
+pub enum OptLevel {
+    Disable,
+    Basic,
+    Extended,
+    All,
+}
+
+impl From<OptLevel> for GraphOptimizationLevel {
+    fn from(level: OptLevel) -> Self {
+        match level {
+            OptLevel::Disable => GraphOptimizationLevel::DisableAll,
+            OptLevel::Basic => GraphOptimizationLevel::Basic,
+            OptLevel::Extended => GraphOptimizationLevel::Extended,
+            OptLevel::All => GraphOptimizationLevel::All,
+        }
     }
-    let mut session = environment
+}
+
+// onnxruntime 0.0.14 only exposes the intra-op thread count. Sessions run in sequential
+// execution mode, where ONNX Runtime does not use an inter-op pool at all.
+#[derive(clap::Args, Debug, Clone)]
+pub struct OnnxOptions {
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i16).range(1..), help = "Threads ONNX Runtime uses inside each operator (intra-op); defaults to all cores. There is no inter-op setting: operators run one after another")]
+    pub onnx_threads: Option<i16>,
+    #[arg(long, value_enum, value_name = "LEVEL", default_value = "basic", help = "ONNX graph optimization level")]
+    pub onnx_opt: OptLevel,
+    #[arg(long, help = "Use the int8-quantized variant of each model (<name>-int8.onnx): smaller and faster on CPU, slightly less accurate")]
+    pub quantized: bool,
+    #[arg(long, help = "Print the inference latency per image")]
+    pub benchmark: bool,
+}
+
+impl Default for OnnxOptions {
+    fn default() -> Self {
+        OnnxOptions { onnx_threads: None, onnx_opt: OptLevel::Basic, quantized: false, benchmark: false }
+    }
+}
+
+pub fn load_session(path: PathBuf, options: &OnnxOptions) -> Result<Session<'static>, String> {
+    let mut builder = environment()?
         .new_session_builder()
         .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to create ONNX session builder: {}", e)))?
-        .with_optimization_level(GraphOptimizationLevel::Basic)
-        .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to set ONNX optimization level: {}", e)))?
-        .with_model_from_file(&model_path)
-        .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to load ONNX model: {}", e)))?;
-
-    let outputs: Vec<OrtOwnedTensor<f32, _>> = session.run(vec![input])
-        .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("ONNX inference failed: {}", e)))?;
-    let mask = outputs[0].view().to_owned()
-        .into_dimensionality::<ndarray::Ix4>()
-        .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to convert ONNX output: {}", e)))?
-        .index_axis_move(ndarray::Axis(0), 0)
-        .index_axis_move(ndarray::Axis(0), 0);
-
-    println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), "ONNX inference completed.");
-    Ok(mask)
-}
\ No newline at end of file
+        .with_optimization_level(options.onnx_opt.into())
+        .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to set ONNX optimization level: {}", e)))?;
+    if let Some(threads) = options.onnx_threads {
+        builder = builder.with_number_threads(threads)
+            .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to set ONNX thread count: {}", e)))?;
+    }
+    let display = path.display().to_string();
+    builder.with_model_from_file(path)
+        .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to load ONNX model '{}': {}. The file may be truncated or not an ONNX model; check it with `image_converter models verify`.", display, e)))
+}
+
+fn shape(dimensions: &[Option<u32>]) -> String {
+    let dims: Vec<String> = dimensions.iter().map(|d| d.map_or("?".to_string(), |d| d.to_string())).collect();
+    format!("[{}]", dims.join(", "))
+}
+
+fn check_shapes(session: &Session, model: &ResolvedModel) -> Result<(), String> {
+    let spec = model.spec;
+    let input = session.inputs.first()
+        .ok_or_else(|| format!("Model '{}' has no inputs.", model.path.display()))?;
+    let dims = &input.dimensions;
+    let size = Some(spec.input_size);
+    if dims.len() != 4 || dims[1].is_some_and(|c| c != 3) || dims[2].is_some_and(|h| Some(h) != size) || dims[3].is_some_and(|w| Some(w) != size) {
+        return Err(format!("Model '{}' takes input {} but '{}' expects [?, 3, {}, {}]. Is this the right file, or does it need a different --model-type?",
+            model.path.display(), shape(dims), spec.name, spec.input_size, spec.input_size));
+    }
+    if spec.output.index >= session.outputs.len() {
+        return Err(format!("Model '{}' expects output #{} but the file only has {} outputs.", spec.name, spec.output.index, session.outputs.len()));
+    }
+    let output = &session.outputs[spec.output.index].dimensions;
+    if output.len() != 4 || output[1].is_some_and(|c| c != 1) {
+        return Err(format!("Model '{}' returns {} but '{}' expects a single-channel mask [?, 1, H, W].", model.path.display(), shape(output), spec.name));
+    }
+    Ok(())
+}
+
+pub fn print_latency(images: usize, elapsed: Duration) {
+    let total = elapsed.as_secs_f64() * 1000.0;
+    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Inference took {:.1} ms ({:.1} ms per image)", total, total / images.max(1) as f64));
+}
+
+/// Anything that turns model input tensors into masks: the session itself, or in batch
+/// mode a worker's handle to the thread that owns the session.
+pub trait MaskPredictor {
+    fn spec(&self) -> &'static ModelSpec;
+
+    fn run_batch(&mut self, inputs: Vec<Array4<f32>>) -> Result<Vec<Array2<f32>>, Box<dyn std::error::Error>>;
+
+    fn run(&mut self, input: Array4<f32>) -> Result<Array2<f32>, Box<dyn std::error::Error>> {
+        self.run_batch(vec![input])?.pop().ok_or_else(|| "ONNX model returned no mask".into())
+    }
+}
+
+pub struct BackgroundRemover {
+    pub model: ResolvedModel,
+    session: Session<'static>,
+    dynamic_batch: bool,
+    benchmark: bool,
+    images: usize,
+    elapsed: Duration,
+}
+
+impl BackgroundRemover {
+    pub fn new(model: ResolvedModel, options: &OnnxOptions) -> Result<Self, Box<dyn std::error::Error>> {
+        println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Loading ONNX model '{}' from {}", model.spec.name, model.path.display()));
+        if !model.path.exists() {
+            return Err(format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Model file not found at {:?}.", model.path)).into());
+        }
+        let session = load_session(model.path.clone(), options)?;
+        check_shapes(&session, &model)
+            .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), e))?;
+        let dynamic_batch = session.inputs.first()
+            .and_then(|input| input.dimensions.first())
+            .is_some_and(|batch| batch.is_none());
+        println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("ONNX model loaded{}.", if dynamic_batch { " (dynamic batch size)" } else { "" }));
+        Ok(BackgroundRemover { model, session, dynamic_batch, benchmark: options.benchmark, images: 0, elapsed: Duration::ZERO })
+    }
+
+    pub fn from_options(options: &ModelOptions, onnx: &OnnxOptions) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new(models::resolve(options, onnx.quantized)?, onnx)
+    }
+
+    pub fn print_benchmark_summary(&self) {
+        if self.benchmark && self.images > 0 {
+            let average = self.elapsed.as_secs_f64() * 1000.0 / self.images as f64;
+            println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Model '{}': {} images, {:.1} ms average inference latency", self.model.spec.name, self.images, average));
+        }
+    }
+
+    pub fn supports_batching(&self) -> bool {
+        self.dynamic_batch
+    }
+
+    pub fn run(&mut self, input: Array4<f32>) -> Result<Array2<f32>, Box<dyn std::error::Error>> {
+        let mut masks = self.run_tensor(input)?;
+        println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), "ONNX inference completed.");
+        masks.pop().ok_or_else(|| "ONNX model returned no mask".into())
+    }
+
+    pub fn run_batch(&mut self, inputs: Vec<Array4<f32>>) -> Result<Vec<Array2<f32>>, Box<dyn std::error::Error>> {
+        if inputs.len() <= 1 || !self.dynamic_batch {
+            return inputs.into_iter().map(|input| self.run(input)).collect();
+        }
+        let count = inputs.len();
+        let views: Vec<_> = inputs.iter().map(|input| input.view()).collect();
+        let batch = ndarray::concatenate(Axis(0), &views)
+            .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to stack input tensors: {}", e)))?;
+        let masks = self.run_tensor(batch)?;
+        println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("ONNX inference completed for {} images.", count));
+        Ok(masks)
+    }
+
+    fn run_tensor(&mut self, input: Array4<f32>) -> Result<Vec<Array2<f32>>, Box<dyn std::error::Error>> {
+        let images = input.dim().0;
+        let started = Instant::now();
+        let outputs: Vec<OrtOwnedTensor<f32, _>> = self.session.run(vec![input])
+            .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("ONNX inference failed: {}", e)))?;
+        let elapsed = started.elapsed();
+        self.images += images;
+        self.elapsed += elapsed;
+        if self.benchmark {
+            print_latency(images, elapsed);
+        }
+        let output_spec = &self.model.spec.output;
+        let output = outputs[output_spec.index].view().to_owned()
+            .into_dimensionality::<ndarray::Ix4>()
+            .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to convert ONNX output: {}", e)))?;
+        Ok(output.outer_iter()
+            .map(|item| postprocess(item.index_axis(Axis(0), 0).to_owned(), output_spec))
+            .collect())
+    }
+}
+
+impl MaskPredictor for BackgroundRemover {
+    fn spec(&self) -> &'static ModelSpec {
+        self.model.spec
+    }
+
+    fn run_batch(&mut self, inputs: Vec<Array4<f32>>) -> Result<Vec<Array2<f32>>, Box<dyn std::error::Error>> {
+        BackgroundRemover::run_batch(self, inputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    const SIGMOID_ONLY: OutputSpec = OutputSpec { index: 0, sigmoid: true, min_max: false };
+    const MIN_MAX_ONLY: OutputSpec = OutputSpec { index: 0, sigmoid: false, min_max: true };
+
+    #[test]
+    fn sigmoid_maps_logits_to_probabilities() {
+        let mask = postprocess(array![[0.0, 20.0], [-20.0, 2.0]], &SIGMOID_ONLY);
+        assert!((mask[[0, 0]] - 0.5).abs() < 1e-6);
+        assert!(mask[[0, 1]] > 0.999);
+        assert!(mask[[1, 0]] < 0.001);
+        assert!((mask[[1, 1]] - 1.0 / (1.0 + (-2.0f32).exp())).abs() < 1e-6);
+    }
+
+    #[test]
+    fn min_max_stretches_to_the_unit_range() {
+        let mask = postprocess(array![[0.2, 0.4], [0.6, 0.3]], &MIN_MAX_ONLY);
+        assert!((mask[[0, 0]] - 0.0).abs() < 1e-6);
+        assert!((mask[[0, 1]] - 0.5).abs() < 1e-6);
+        assert!((mask[[1, 0]] - 1.0).abs() < 1e-6);
+        assert!((mask[[1, 1]] - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn min_max_leaves_a_flat_mask_alone() {
+        let mask = postprocess(array![[0.7, 0.7], [0.7, 0.7]], &MIN_MAX_ONLY);
+        assert!(mask.iter().all(|&v| (v - 0.7).abs() < 1e-6));
+    }
+}
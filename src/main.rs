@@ -1,4 +1,5 @@
 mod batch_processing;
+mod batch_input;
 use colored::*;
 mod png_to_jpg;
 mod jpg_to_png;
@@ -6,15 +7,31 @@ mod webp_to_jpg;
 mod jpg_to_webp;
 mod png_to_webp;
 mod webp_to_png;
+mod to_gif;
 mod ui;
 mod preprocess;
 mod inference;
 mod apply_mask;
 mod palette_extract;
+mod palette_export;
+mod batch_palette;
+mod placeholder;
+mod models;
+mod pipeline;
+mod adjust;
+mod filters;
+mod bg_replace;
+mod trim;
+mod watermark;
+mod mask_refine;
+mod mask_export;
+mod tiling;
+mod effects;
+mod onnx_image;
+mod model_manager;
+mod remap;
 
-use preprocess::preprocess_image;
-use inference::run_inference;
-use apply_mask::apply_mask;
+use pipeline::ImageOps;
 
 use clap::{Parser, ValueEnum};
 
@@ -29,33 +46,75 @@ use clap::{Parser, ValueEnum};
     - Optionally strip all metadata from output images (pure Rust, no dependencies).\n\
     - Optionally extract a color palette from any image.\n\
     - All logic is pure Rust and self-contained.\n\
-    \nUSAGE:\n  image_converter convert -i input.jpg -o output -f png --remove-bg\n  image_converter batch --input-dir ./indir --output-dir ./outdir -f webp -b -s\n  image_converter\n\nSUBCOMMANDS:\n  convert    Convert a single image file\n  batch      Batch process all images in a directory\n\nSupported formats: jpg, jpeg, png, webp. Background removal only applies to PNG & WebP outputs. Metadata stripping applies to all formats.")]
+    \nUSAGE:\n  image_converter convert -i input.jpg -o output -f png --remove-bg\n  image_converter batch --input-dir ./indir --output-dir ./outdir -f webp -b -s\n  image_converter batch --input-dir ./shoot --output-dir ./out -f jpg --palette --colors 8\n  image_converter batch --input-dir ./assets --output-dir ./out -f webp --recursive --max-depth 2\n  image_converter batch --input-dir ./repo --output-dir ./out -f webp -r --include '**/*.png' --exclude '**/thumbs/**' --min-width 800\n  image_converter palette photo.jpg --colors 8\n  image_converter convert -i photo.jpg -o brand -f png --remap brand.gpl --dither floyd-steinberg\n  image_converter\n\nSUBCOMMANDS:\n  convert    Convert a single image file\n  batch      Batch process all images in a directory\n  palette    Print the color palette of an image\n  models     List, verify and install ONNX models\n\nSupported formats: jpg, jpeg, png, webp (input and output) and gif (output). Background removal only applies to PNG, WebP & GIF outputs unless a replacement background is given. Metadata stripping applies to all formats.")]
 enum Cli {
     Convert {
         #[arg(short, long, value_name = "FILE", help = "Input image file path (required)")]
         input: String,
         #[arg(short, long, value_name = "PATH", help = "Output file path or directory (required)")]
         output: String,
-        #[arg(short, long, value_enum, value_name = "FORMAT", help = "Output format: jpg, png, or webp (required)")]
+        #[arg(short, long, value_enum, value_name = "FORMAT", help = "Output format: jpg, png, webp or gif (required)")]
         format: Format,
-        #[arg(short = 'b', long, help = "Remove background (PNG/WebP output only)")]
+        #[arg(short = 'b', long, help = "Remove background (PNG/WebP output, or any format with --bg-replace/--bg-blur)")]
         remove_bg: bool,
         #[arg(short = 's', long, help = "Strip all metadata from the output image (pure Rust, all formats)")]
         strip_metadata: bool,
         #[arg(short = 'p', long, help = "Extract and display a color palette from the input image")]
         palette: bool,
+        #[command(flatten)]
+        palette_options: palette_extract::PaletteOptions,
+        #[command(flatten)]
+        palette_export: palette_export::PaletteExport,
+        #[command(flatten)]
+        placeholder_options: placeholder::PlaceholderOptions,
+        #[command(flatten)]
+        ops: ImageOps,
     },
     Batch {
         #[arg(long, value_name = "DIR", help = "Input directory (required)")]
         input_dir: String,
         #[arg(long, value_name = "DIR", help = "Output directory (required)")]
         output_dir: String,
-        #[arg(short, long, value_enum, value_name = "FORMAT", help = "Output format: jpg, png, or webp (required)")]
+        #[arg(short, long, value_enum, value_name = "FORMAT", help = "Output format: jpg, png, webp or gif (required)")]
         format: Format,
-        #[arg(short = 'b', long, help = "Remove background (PNG/WebP output only)")]
+        #[arg(short = 'b', long, help = "Remove background (PNG/WebP output, or any format with --bg-replace/--bg-blur)")]
         remove_bg: bool,
         #[arg(short = 's', long, help = "Strip all metadata from the output images (pure Rust, all formats)")]
         strip_metadata: bool,
+        #[arg(long, value_name = "N", default_value_t = 4, help = "Images per ONNX run during background removal (when the model has a dynamic batch axis)")]
+        bg_batch_size: usize,
+        #[arg(short = 'j', long, value_name = "N", default_value_t = batch_processing::default_jobs() as u32, value_parser = clap::value_parser!(u32).range(1..), help = "Number of files to convert in parallel (default: the number of CPUs)")]
+        jobs: u32,
+        #[command(flatten)]
+        input: batch_input::InputOptions,
+        #[arg(short = 'p', long, help = "Build one palette for the whole collection plus per-image palettes from the processed images")]
+        palette: bool,
+        #[arg(long, value_name = "FILE", requires = "palette", help = "Where to write the JSON palette report (default: <output-dir>/palette-report.json)")]
+        palette_report: Option<String>,
+        #[command(flatten)]
+        palette_options: palette_extract::PaletteOptions,
+        #[command(flatten)]
+        palette_export: palette_export::PaletteExport,
+        #[command(flatten)]
+        placeholder_options: placeholder::PlaceholderOptions,
+        #[arg(long, value_name = "FILE", requires = "placeholders", help = "Where to write the placeholder manifest (default: <output-dir>/placeholders.json)")]
+        placeholder_manifest: Option<String>,
+        #[command(flatten)]
+        ops: ImageOps,
+    },
+    /// Extract a color palette from an image without converting it
+    Palette {
+        #[arg(value_name = "FILE", help = "Image to extract the palette from")]
+        file: String,
+        #[command(flatten)]
+        palette_options: palette_extract::PaletteOptions,
+        #[command(flatten)]
+        export: palette_export::PaletteExport,
+    },
+    /// List, verify and install ONNX models
+    Models {
+        #[command(subcommand)]
+        command: Option<model_manager::ModelsCommand>,
     },
 }
 
@@ -65,16 +124,18 @@ enum Format {
     Jpg,
     Png,
     Webp,
+    Gif,
 }
 
-fn convert_single_file(input_path: String, output_base: String, output_ext: &str, remove_bg: bool, strip_metadata: bool) {
+fn convert_single_file(input_path: String, output_base: String, output_ext: &str, remove_bg: bool, strip_metadata: bool, ops: &ImageOps) {
     use std::fs;
+    use std::path::Path;
     use std::process;
     let input_ext = input_path.split('.').last().unwrap_or("").to_lowercase();
     let input_ext = if input_ext == "jpeg" { "jpg".to_string() } else { input_ext };
     let output_ext = if output_ext == "jpeg" { "jpg" } else { output_ext };
     let output_file = format!("{}.{}", output_base, output_ext);
-    let do_copy = input_ext == output_ext && !remove_bg && !strip_metadata;
+    let do_copy = input_ext == output_ext && !remove_bg && !strip_metadata && ops.is_empty();
     if do_copy {
         match fs::copy(&input_path, &output_file) {
             Ok(_) => println!("\n{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("No conversion needed. File copied as {}", output_file)),
@@ -85,6 +146,13 @@ fn convert_single_file(input_path: String, output_base: String, output_ext: &str
         }
         return;
     }
+    let mut models = match ops.enhance.load(&ops.onnx) {
+        Ok(models) => models,
+        Err(e) => {
+            eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), e);
+            process::exit(1);
+        }
+    };
     println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Starting conversion: {} -> {} ({} -> {})", input_path, output_file, input_ext, output_ext));
     let input_is_jpg = input_ext == "jpg" || input_ext == "jpeg";
     let output_is_jpg = output_ext == "jpg" || output_ext == "jpeg";
@@ -98,6 +166,7 @@ fn convert_single_file(input_path: String, output_base: String, output_ext: &str
             ((inp, "webp"), (true, _)) if inp == "jpg" || inp == "jpeg" => jpg_to_webp::jpg_to_webp(&input_path, &output_file),
             (("png", "webp"), _) => png_to_webp::png_to_webp(&input_path, &output_file),
             (("webp", "png"), _) => webp_to_png::webp_to_png(&input_path, &output_file),
+            ((_, "gif"), _) => to_gif::to_gif(&input_path, &output_file),
             _ => Err(format!("[ERROR] Conversion from {} to {} is not supported.", input_ext, output_ext)),
         }
     };
@@ -110,15 +179,24 @@ fn convert_single_file(input_path: String, output_base: String, output_ext: &str
             process::exit(1);
         }
     }
-    if (output_ext == "png" || output_ext == "webp") && remove_bg {
+    let mut bg_removed = false;
+    let bg_supported = output_ext == "png" || output_ext == "webp" || output_ext == "gif" || !ops.needs_alpha_output();
+    if bg_supported && remove_bg {
         println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), "Attempting background removal...");
-        match preprocess_image(&input_path)
-            .and_then(|input_tensor| run_inference(input_tensor))
-            .and_then(|mask| apply_mask(&input_path, mask, &output_file)) {
-            Ok(_) => println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("Background removed and saved as {}", output_file)),
+        match inference::BackgroundRemover::from_options(&ops.model, &ops.onnx)
+            .map_err(|e| e.to_string())
+            .and_then(|mut remover| pipeline::remove_background(&input_path, &output_file, Path::new(""), output_ext, ops, &mut remover, &mut models)) {
+            Ok(_) => {
+                bg_removed = true;
+                println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("Background removed and saved as {}", output_file));
+            }
             Err(e) => eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Background removal failed: {}", e)),
         }
     }
+    if !bg_removed && !ops.is_empty() && let Err(e) = pipeline::apply_operations(&input_path, &output_file, output_ext, ops, &mut models) {
+        eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), e);
+        process::exit(1);
+    }
 }
 
 fn flush_stdin() {
@@ -130,20 +208,41 @@ fn flush_stdin() {
     }
 }
 
+fn print_palette(path: &str, options: &palette_extract::PaletteOptions, export: &palette_export::PaletteExport) {
+    let result = palette_extract::palette_from_file(path, options).and_then(|palette| {
+        palette_extract::print_palette(&palette);
+        if options.palette_contrast {
+            palette_extract::print_contrast_report(&palette, options.apca);
+        }
+        match export.target(path)? {
+            Some((out, format)) => {
+                palette_export::write_palette(&palette, &out, format)?;
+                println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("Palette written to {}", out.display()));
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), e);
+        std::process::exit(1);
+    }
+}
+
 fn main() {
     let arg_count = std::env::args().len();
     if arg_count == 1 {
         loop {
             match ui::main_menu() {
                 ui::TuiAction::SingleFile => {
-                    let (input_path, output_base, format_index, remove_bg, strip_metadata) = ui::get_user_input();
+                    let (input_path, output_base, format_index, remove_bg, strip_metadata, ops) = ui::get_user_input();
                     let output_ext = match format_index {
                         0 => "jpg",
                         1 => "png",
                         2 => "webp",
                         _ => "jpg",
                     };
-                    convert_single_file(input_path, output_base, output_ext, remove_bg, strip_metadata);
+                    convert_single_file(input_path, output_base, output_ext, remove_bg, strip_metadata, &ops);
                 }
                 ui::TuiAction::Batch => {
                     if let Some(batch_opts) = ui::get_batch_options() {
@@ -153,6 +252,12 @@ fn main() {
                             format_index: batch_opts.format_index,
                             remove_bg: batch_opts.remove_bg,
                             strip_metadata: batch_opts.strip_metadata,
+                            ops: batch_opts.ops,
+                            bg_batch_size: 4,
+                            palette: None,
+                            placeholders: None,
+                            jobs: batch_processing::default_jobs(),
+                            input: batch_input::InputOptions::default(),
                         };
                         job.run();
                     }
@@ -167,11 +272,12 @@ fn main() {
     }
 
     let (input_path, output_base, output_ext, remove_bg, strip_metadata, _palette) = match Cli::parse() {
-        Cli::Convert { input, output, format, remove_bg, strip_metadata, palette: _ } => {
+        Cli::Convert { input, output, format, remove_bg, strip_metadata, palette, palette_options, palette_export, placeholder_options, ops } => {
             let ext = match format {
                 Format::Jpg => "jpg",
                 Format::Png => "png",
                 Format::Webp => "webp",
+                Format::Gif => "gif",
             };
             let input_path = input;
             let output_arg = output;
@@ -196,14 +302,28 @@ fn main() {
                     out.to_string_lossy().to_string()
                 }
             };
-            convert_single_file(input_path, output_base, ext, remove_bg, strip_metadata);
+            if palette || palette_export.is_enabled() {
+                print_palette(&input_path, &palette_options, &palette_export);
+            }
+            let output_file = format!("{}.{}", output_base, ext);
+            convert_single_file(input_path, output_base, ext, remove_bg, strip_metadata, &ops);
+            if placeholder_options.placeholders {
+                match placeholder::compute(&output_file, &placeholder_options) {
+                    Ok(placeholders) => placeholder::print_placeholders(&placeholders),
+                    Err(e) => {
+                        eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), e);
+                        std::process::exit(1);
+                    }
+                }
+            }
             (String::new(), String::new(), "", false, false, false)
         }
-        Cli::Batch { input_dir, output_dir, format, remove_bg, strip_metadata } => {
+        Cli::Batch { input_dir, output_dir, format, remove_bg, strip_metadata, bg_batch_size, jobs, input, palette, palette_report, palette_options, palette_export, placeholder_options, placeholder_manifest, ops } => {
             let format_index = match format {
                 Format::Jpg => 0,
                 Format::Png => 1,
                 Format::Webp => 2,
+                Format::Gif => 3,
             };
             let job = batch_processing::BatchJob {
                 input_dir,
@@ -211,9 +331,30 @@ fn main() {
                 format_index,
                 remove_bg,
                 strip_metadata,
+                ops,
+                bg_batch_size,
+                palette: (palette || palette_export.is_enabled()).then_some(batch_palette::BatchPalette {
+                    options: palette_options,
+                    export: palette_export,
+                    report: palette_report,
+                }),
+                placeholders: placeholder_options.placeholders.then_some(placeholder::PlaceholderManifest {
+                    options: placeholder_options,
+                    manifest: placeholder_manifest,
+                }),
+                jobs: jobs as usize,
+                input,
             };
             job.run();
             std::process::exit(0);
         }
+        Cli::Palette { file, palette_options, export } => {
+            print_palette(&file, &palette_options, &export);
+            std::process::exit(0);
+        }
+        Cli::Models { command } => {
+            let ok = model_manager::run(command.unwrap_or(model_manager::ModelsCommand::List));
+            std::process::exit(if ok { 0 } else { 1 });
+        }
     };
 }
\ No newline at end of file
@@ -0,0 +1,176 @@
+use image::{DynamicImage, GrayImage, Luma, RgbaImage};
+use colored::*;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SUBJECT_ALPHA: u8 = 16;
+
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct MaskExport {
+    #[arg(long, value_name = "FILE|DIR", requires = "remove_bg", help = "Also write the full-resolution grayscale mask (in batch mode pass a directory)")]
+    pub mask_out: Option<String>,
+    #[arg(long, requires = "remove_bg", conflicts_with_all = ["bg_replace", "bg_blur"], help = "Write the grayscale mask as the output image instead of the cutout")]
+    pub mask_only: bool,
+    #[arg(long, value_name = "FILE|DIR|-", requires = "remove_bg", help = "Write the subject bounding box and mask coverage as JSON ('-' prints to stdout)")]
+    pub mask_report: Option<String>,
+    #[arg(long, requires = "remove_bg", help = "Crop the output to the subject bounding box")]
+    pub crop_subject: bool,
+    #[arg(long, value_name = "PX", default_value_t = 0, requires = "crop_subject", help = "Margin in pixels to keep around the subject when cropping")]
+    pub crop_margin: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SubjectReport {
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+    pub coverage_percent: f32,
+    pub bbox: Option<BoundingBox>,
+}
+
+pub fn alpha_channel(cutout: &RgbaImage) -> GrayImage {
+    GrayImage::from_fn(cutout.width(), cutout.height(), |x, y| Luma([cutout.get_pixel(x, y)[3]]))
+}
+
+pub fn subject_bbox(mask: &GrayImage) -> Option<BoundingBox> {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in mask.enumerate_pixels() {
+        if pixel[0] >= SUBJECT_ALPHA {
+            bounds = Some(match bounds {
+                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                None => (x, y, x, y),
+            });
+        }
+    }
+    bounds.map(|(x0, y0, x1, y1)| BoundingBox { x: x0, y: y0, width: x1 - x0 + 1, height: y1 - y0 + 1 })
+}
+
+pub fn subject_report(image: &str, mask: &GrayImage) -> SubjectReport {
+    let (width, height) = mask.dimensions();
+    let alpha_sum: u64 = mask.pixels().map(|p| p[0] as u64).sum();
+    let pixels = (width as u64 * height as u64).max(1);
+    SubjectReport {
+        image: image.to_string(),
+        width,
+        height,
+        coverage_percent: (alpha_sum as f64 / (pixels * 255) as f64 * 100.0) as f32,
+        bbox: subject_bbox(mask),
+    }
+}
+
+pub fn crop_to_subject(img: DynamicImage, bbox: Option<BoundingBox>, margin: u32) -> DynamicImage {
+    let Some(bbox) = bbox else {
+        println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), "No subject found in the mask, skipping crop.");
+        return img;
+    };
+    let x = bbox.x.saturating_sub(margin);
+    let y = bbox.y.saturating_sub(margin);
+    let right = (bbox.x + bbox.width).saturating_add(margin).min(img.width());
+    let bottom = (bbox.y + bbox.height).saturating_add(margin).min(img.height());
+    img.crop_imm(x, y, right - x, bottom - y)
+}
+
+/// A directory target gets one file per output image, named after the output. In recursive
+/// batches `relative_dir` recreates the input's subdirectory so same-named files don't collide.
+fn target_path(target: &str, output_path: &str, relative_dir: &Path, ext: &str) -> Result<PathBuf, String> {
+    let target = Path::new(target);
+    if !target.is_dir() {
+        return Ok(target.to_path_buf());
+    }
+    let dir = target.join(relative_dir);
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to create directory '{}': {}", dir.display(), e)))?;
+    let stem = Path::new(output_path).file_stem().unwrap_or_default();
+    Ok(dir.join(stem).with_extension(ext))
+}
+
+impl MaskExport {
+    /// Batch mode writes one mask and report per image, so the targets must be directories:
+    /// missing ones are created and existing files are refused.
+    pub fn prepare_batch(&self) -> Result<(), String> {
+        let targets = [self.mask_out.as_deref(), self.mask_report.as_deref().filter(|target| *target != "-")];
+        for target in targets.into_iter().flatten() {
+            let dir = Path::new(target);
+            if dir.exists() && !dir.is_dir() {
+                return Err(format!("'{}' is a file; in batch mode --mask-out and --mask-report take a directory", dir.display()));
+            }
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory '{}': {}", dir.display(), e))?;
+        }
+        Ok(())
+    }
+
+    pub fn write_mask(&self, mask: &GrayImage, output_path: &str, relative_dir: &Path) -> Result<(), String> {
+        let Some(target) = &self.mask_out else {
+            return Ok(());
+        };
+        let path = target_path(target, output_path, relative_dir, "png")?;
+        mask.save(&path)
+            .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to save mask '{}': {}", path.display(), e)))?;
+        println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("Mask written to {}", path.display()));
+        Ok(())
+    }
+
+    pub fn write_report(&self, report: &SubjectReport, output_path: &str, relative_dir: &Path) -> Result<(), String> {
+        let Some(target) = &self.mask_report else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(report).map_err(|e| e.to_string())?;
+        if target == "-" {
+            println!("{}", json);
+            return Ok(());
+        }
+        let path = target_path(target, output_path, relative_dir, "json")?;
+        fs::write(&path, json)
+            .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to write mask report '{}': {}", path.display(), e)))?;
+        println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("Mask report written to {}", path.display()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directory_targets_mirror_the_relative_dir() {
+        let root = std::env::temp_dir().join(format!("mask-export-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let target = root.display().to_string();
+        let first = target_path(&target, "out/a/en/hero.png", Path::new("a/en"), "png").unwrap();
+        let second = target_path(&target, "out/b/en/hero.png", Path::new("b/en"), "png").unwrap();
+        assert_eq!(first, root.join("a/en/hero.png"));
+        assert_eq!(second, root.join("b/en/hero.png"));
+        assert!(root.join("b/en").is_dir());
+        assert_eq!(target_path(&target, "out/hero.png", Path::new(""), "json").unwrap(), root.join("hero.json"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn file_targets_are_used_as_is() {
+        let path = target_path("mask.png", "out/a/hero.png", Path::new("a"), "png").unwrap();
+        assert_eq!(path, PathBuf::from("mask.png"));
+    }
+
+    #[test]
+    fn batch_targets_must_be_directories() {
+        let root = std::env::temp_dir().join(format!("mask-export-batch-{}", std::process::id()));
+        let masks = root.join("masks");
+        let export = MaskExport { mask_out: Some(masks.display().to_string()), mask_report: Some("-".to_string()), ..Default::default() };
+        export.prepare_batch().unwrap();
+        assert!(masks.is_dir());
+        let file = root.join("report.json");
+        fs::write(&file, "{}").unwrap();
+        let export = MaskExport { mask_report: Some(file.display().to_string()), ..Default::default() };
+        assert!(export.prepare_batch().is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
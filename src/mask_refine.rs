@@ -0,0 +1,196 @@
+use image::{imageops, ImageBuffer, Luma, RgbImage};
+
+pub type AlphaMask = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Debug)]
+#[clap(rename_all = "lower")]
+pub enum Matting {
+    Guided,
+}
+
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct MaskOptions {
+    #[arg(long, value_name = "0-1", value_parser = parse_threshold, requires = "remove_bg", help = "Make the mask binary: alpha above the threshold becomes opaque, the rest transparent")]
+    pub mask_threshold: Option<f32>,
+    #[arg(long, value_name = "PX", default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=1000), requires = "remove_bg", help = "Shrink the mask by this many pixels (0-1000)")]
+    pub erode: u32,
+    #[arg(long, value_name = "PX", default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=1000), requires = "remove_bg", help = "Grow the mask by this many pixels (0-1000)")]
+    pub dilate: u32,
+    #[arg(long, value_name = "PX", value_parser = parse_feather, requires = "remove_bg", help = "Soften the mask edge over roughly this many pixels")]
+    pub feather: Option<f32>,
+    #[arg(long, value_enum, value_name = "METHOD", requires = "remove_bg", help = "Refine the mask edge against the full-resolution original (helps with hair and fur)")]
+    pub matting: Option<Matting>,
+    #[arg(long, value_name = "PX", default_value_t = 8, requires = "matting", help = "Window radius used by --matting")]
+    pub matting_radius: u32,
+    #[arg(long, requires = "remove_bg", help = "Remove background color spill from semi-transparent edge pixels")]
+    pub decontaminate: bool,
+}
+
+fn parse_threshold(value: &str) -> Result<f32, String> {
+    let threshold: f32 = value.parse()
+        .map_err(|_| format!("'{}' is not a number between 0 and 1", value))?;
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(format!("'{}' must be between 0 and 1", value));
+    }
+    Ok(threshold)
+}
+
+fn parse_feather(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(feather) if feather.is_finite() && feather >= 0.0 => Ok(feather),
+        _ => Err(format!("'{}' is not a valid feather width (must be a number of pixels, 0 or more)", value)),
+    }
+}
+
+impl MaskOptions {
+    pub fn refines_mask(&self) -> bool {
+        self.mask_threshold.is_some() || self.erode > 0 || self.dilate > 0 || self.feather.is_some() || self.matting.is_some()
+    }
+}
+
+fn box_mean(values: &[f32], width: usize, height: usize, radius: usize) -> Vec<f32> {
+    let mut integral = vec![0.0f64; (width + 1) * (height + 1)];
+    for y in 0..height {
+        let mut row = 0.0f64;
+        for x in 0..width {
+            row += values[y * width + x] as f64;
+            integral[(y + 1) * (width + 1) + x + 1] = integral[y * (width + 1) + x + 1] + row;
+        }
+    }
+    let mut out = vec![0.0f32; width * height];
+    for y in 0..height {
+        let y0 = y.saturating_sub(radius);
+        let y1 = (y + radius + 1).min(height);
+        for x in 0..width {
+            let x0 = x.saturating_sub(radius);
+            let x1 = (x + radius + 1).min(width);
+            let sum = integral[y1 * (width + 1) + x1] - integral[y0 * (width + 1) + x1]
+                - integral[y1 * (width + 1) + x0] + integral[y0 * (width + 1) + x0];
+            out[y * width + x] = (sum / ((x1 - x0) * (y1 - y0)) as f64) as f32;
+        }
+    }
+    out
+}
+
+fn guided_filter(original: &RgbImage, alpha: &AlphaMask, radius: u32) -> AlphaMask {
+    let (width, height) = alpha.dimensions();
+    let (w, h, r) = (width as usize, height as usize, radius as usize);
+    let eps = 1e-3f32;
+    let guide: Vec<f32> = original.pixels()
+        .map(|p| (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32) / 255.0)
+        .collect();
+    let p: &[f32] = alpha.as_raw();
+    let ip: Vec<f32> = guide.iter().zip(p).map(|(i, p)| i * p).collect();
+    let ii: Vec<f32> = guide.iter().map(|i| i * i).collect();
+    let mean_i = box_mean(&guide, w, h, r);
+    let mean_p = box_mean(p, w, h, r);
+    let mean_ip = box_mean(&ip, w, h, r);
+    let mean_ii = box_mean(&ii, w, h, r);
+    let mut a = vec![0.0f32; w * h];
+    let mut b = vec![0.0f32; w * h];
+    for k in 0..w * h {
+        let cov = mean_ip[k] - mean_i[k] * mean_p[k];
+        let var = mean_ii[k] - mean_i[k] * mean_i[k];
+        a[k] = cov / (var + eps);
+        b[k] = mean_p[k] - a[k] * mean_i[k];
+    }
+    let mean_a = box_mean(&a, w, h, r);
+    let mean_b = box_mean(&b, w, h, r);
+    let refined = (0..w * h)
+        .map(|k| (mean_a[k] * guide[k] + mean_b[k]).clamp(0.0, 1.0))
+        .collect();
+    AlphaMask::from_raw(width, height, refined).expect("buffer matches mask dimensions")
+}
+
+fn min_max_filter(alpha: &AlphaMask, radius: u32, grow: bool) -> AlphaMask {
+    let (width, height) = alpha.dimensions();
+    let pick = |a: f32, b: f32| if grow { a.max(b) } else { a.min(b) };
+    let mut horizontal = alpha.clone();
+    for y in 0..height {
+        for x in 0..width {
+            let x0 = x.saturating_sub(radius);
+            let x1 = x.saturating_add(radius).min(width - 1);
+            let value = (x0..=x1).map(|nx| alpha.get_pixel(nx, y)[0]).reduce(pick).unwrap_or(0.0);
+            horizontal.put_pixel(x, y, Luma([value]));
+        }
+    }
+    let mut out = horizontal.clone();
+    for y in 0..height {
+        let y0 = y.saturating_sub(radius);
+        let y1 = y.saturating_add(radius).min(height - 1);
+        for x in 0..width {
+            let value = (y0..=y1).map(|ny| horizontal.get_pixel(x, ny)[0]).reduce(pick).unwrap_or(0.0);
+            out.put_pixel(x, y, Luma([value]));
+        }
+    }
+    out
+}
+
+pub fn refine(original: &RgbImage, mut alpha: AlphaMask, options: &MaskOptions) -> AlphaMask {
+    if alpha.width() == 0 || alpha.height() == 0 {
+        return alpha;
+    }
+    if let Some(Matting::Guided) = options.matting {
+        alpha = guided_filter(original, &alpha, options.matting_radius.max(1));
+    }
+    if let Some(threshold) = options.mask_threshold {
+        for pixel in alpha.pixels_mut() {
+            pixel[0] = if pixel[0] > threshold { 1.0 } else { 0.0 };
+        }
+    }
+    if options.erode > 0 {
+        alpha = min_max_filter(&alpha, options.erode, false);
+    }
+    if options.dilate > 0 {
+        alpha = min_max_filter(&alpha, options.dilate, true);
+    }
+    if let Some(feather) = options.feather.filter(|f| *f > 0.0) {
+        alpha = imageops::blur(&alpha, feather / 2.0);
+    }
+    alpha
+}
+
+pub fn decontaminate(original: &RgbImage, alpha: &AlphaMask) -> RgbImage {
+    let (width, height) = original.dimensions();
+    let sigma = (width.max(height) as f32 / 100.0).max(4.0);
+    let mut weighted = ImageBuffer::<image::Rgba<f32>, Vec<f32>>::new(width, height);
+    for (x, y, pixel) in weighted.enumerate_pixels_mut() {
+        let background_weight = 1.0 - alpha.get_pixel(x, y)[0];
+        let p = original.get_pixel(x, y);
+        *pixel = image::Rgba([
+            p[0] as f32 * background_weight,
+            p[1] as f32 * background_weight,
+            p[2] as f32 * background_weight,
+            background_weight,
+        ]);
+    }
+    let background = imageops::blur(&weighted, sigma);
+
+    let mut out = original.clone();
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let a = alpha.get_pixel(x, y)[0];
+        let bg = background.get_pixel(x, y);
+        if a <= 0.0 || a >= 1.0 || bg[3] <= f32::EPSILON {
+            continue;
+        }
+        for c in 0..3 {
+            let background_color = bg[c] / bg[3];
+            let foreground = (pixel[c] as f32 - (1.0 - a) * background_color) / a.max(0.05);
+            pixel[c] = foreground.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_radius_covers_the_whole_mask() {
+        let mut alpha = AlphaMask::new(4, 3);
+        alpha.put_pixel(1, 1, Luma([1.0]));
+        assert!(min_max_filter(&alpha, u32::MAX, true).pixels().all(|p| p[0] == 1.0));
+        assert!(min_max_filter(&alpha, u32::MAX, false).pixels().all(|p| p[0] == 0.0));
+    }
+}
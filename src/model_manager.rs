@@ -0,0 +1,364 @@
+use colored::*;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::models::{self, REGISTRY};
+use crate::onnx_image::IMAGE_MODELS;
+
+const MANIFEST: &str = include_str!("../assets/models.sha256");
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ModelsCommand {
+    /// List known models and where they were found
+    List,
+    /// Show the directories models are looked up in
+    Paths,
+    /// Check installed model files against the embedded SHA-256 manifest
+    Verify {
+        #[arg(value_name = "NAME|FILE", help = "Model names or .onnx files to check (default: every installed model)")]
+        models: Vec<String>,
+    },
+    /// Install a model from a local .onnx file or a .zip/.tar/.tar.gz archive
+    Install {
+        #[arg(value_name = "FILE", help = "A .onnx file or an archive containing .onnx files")]
+        source: String,
+        #[arg(long, value_name = "NAME", help = "Install a single .onnx file under a known model's file name, e.g. u2net")]
+        name: Option<String>,
+        #[arg(long, value_name = "DIR", help = "Target directory (default: the user data directory shown by `models paths`)")]
+        dir: Option<String>,
+        #[arg(long, help = "Overwrite existing files and install even if the checksum does not match the manifest")]
+        force: bool,
+    },
+}
+
+/// All model files this build knows about: (name, file name).
+fn known_files() -> Vec<(&'static str, String)> {
+    let mut files = vec![];
+    for (name, file_name) in REGISTRY.iter().map(|s| (s.name, s.file_name)).chain(IMAGE_MODELS.iter().map(|d| (d.name, d.file_name))) {
+        files.push((name, file_name.to_string()));
+        files.push((name, models::quantized_file_name(file_name)));
+    }
+    files
+}
+
+fn is_known_file(path: &Path) -> bool {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    known_files().iter().any(|(_, known)| known == file_name)
+}
+
+fn expected_checksum<'a>(manifest: &'a str, file_name: &str) -> Option<&'a str> {
+    manifest.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, file)| file.trim() == file_name)
+        .map(|(hash, _)| hash)
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+pub enum Integrity<'a> {
+    Verified,
+    Unknown(String),
+    Mismatch { expected: &'a str, actual: String },
+}
+
+/// Checks `path` against `manifest`, normally the embedded `MANIFEST`.
+pub fn check_integrity<'a>(path: &Path, manifest: &'a str) -> io::Result<Integrity<'a>> {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let actual = sha256_file(path)?;
+    Ok(match expected_checksum(manifest, file_name) {
+        Some(expected) if expected.eq_ignore_ascii_case(&actual) => Integrity::Verified,
+        Some(expected) => Integrity::Mismatch { expected, actual },
+        None => Integrity::Unknown(actual),
+    })
+}
+
+fn default_install_dir() -> Result<PathBuf, String> {
+    models::user_model_dir().ok_or_else(|| "Could not determine the user data directory; pass --dir".to_string())
+}
+
+fn list() {
+    println!("{}", "Known models:".bold());
+    for (name, file_name) in known_files() {
+        let status = match models::find_file(name, &file_name) {
+            Ok(path) => format!("{} {}", "✔".green(), path.display()),
+            Err(_) => format!("{} not installed", "✖".red()),
+        };
+        println!("  {:<20} {:<28} {}", name, file_name, status);
+    }
+    println!("\nRun `image_converter models paths` to see the lookup order.");
+}
+
+fn paths() {
+    println!("{}", "Models are looked up in this order:".bold());
+    if let Some(value) = std::env::var_os(models::MODEL_ENV) {
+        println!("  ${} = {}", models::MODEL_ENV, value.to_string_lossy());
+    }
+    for dir in models::search_dirs() {
+        let marker = if dir.is_dir() { "✔".green() } else { "-".normal() };
+        println!("  {} {}", marker, dir.display());
+    }
+    if let Ok(dir) = default_install_dir() {
+        println!("\n`models install` copies into {}", dir.display());
+    }
+}
+
+fn verify_file(path: &Path, manifest: &str) -> bool {
+    match check_integrity(path, manifest) {
+        Ok(Integrity::Verified) => {
+            println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("{}: checksum OK", path.display()));
+            true
+        }
+        // A file named like one of our models must be checkable; only custom models may be unlisted.
+        Ok(Integrity::Unknown(actual)) if is_known_file(path) => {
+            eprintln!("{} {} {}", "[WARNING]".bold().yellow(), "⚠".yellow(), format!("{}: no checksum in the manifest, so it cannot be verified (sha256 {})", path.display(), actual));
+            false
+        }
+        Ok(Integrity::Unknown(actual)) => {
+            println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("{}: not a known model and not in the manifest (sha256 {})", path.display(), actual));
+            true
+        }
+        Ok(Integrity::Mismatch { expected, actual }) => {
+            eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("{}: checksum mismatch, expected {} got {}. The file is corrupt, truncated or a different model.", path.display(), expected, actual));
+            false
+        }
+        Err(e) => {
+            eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("{}: {}", path.display(), e));
+            false
+        }
+    }
+}
+
+fn verify(requested: &[String]) -> bool {
+    let paths: Vec<PathBuf> = if requested.is_empty() {
+        known_files().into_iter().filter_map(|(name, file_name)| models::find_file(name, &file_name).ok()).collect()
+    } else {
+        let mut paths = vec![];
+        for value in requested {
+            let path = PathBuf::from(value);
+            if path.is_file() {
+                paths.push(path);
+                continue;
+            }
+            match known_files().into_iter().find(|(name, _)| name.eq_ignore_ascii_case(value)) {
+                Some((name, file_name)) => match models::find_file(name, &file_name) {
+                    Ok(path) => paths.push(path),
+                    Err(e) => {
+                        eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), e);
+                        return false;
+                    }
+                },
+                None => {
+                    eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("'{}' is neither a known model nor a file", value));
+                    return false;
+                }
+            }
+        }
+        paths
+    };
+    if paths.is_empty() {
+        println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), "No installed models found. See `image_converter models paths`.");
+        return true;
+    }
+    let results: Vec<bool> = paths.iter().map(|path| verify_file(path, MANIFEST)).collect();
+    results.into_iter().all(|ok| ok)
+}
+
+/// Extracts every .onnx entry of an archive into `staging`, flattening directories.
+fn extract_archive(source: &Path, staging: &Path) -> Result<Vec<PathBuf>, String> {
+    let lower = source.to_string_lossy().to_lowercase();
+    let mut extracted = vec![];
+    if lower.ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(File::open(source).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
+            let Some(file_name) = entry.enclosed_name().and_then(|p| p.file_name().map(|n| n.to_owned())) else {
+                continue;
+            };
+            if entry.is_file() && file_name.to_string_lossy().ends_with(".onnx") {
+                let target = staging.join(file_name);
+                io::copy(&mut entry, &mut File::create(&target).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+                extracted.push(target);
+            }
+        }
+    } else {
+        let file = File::open(source).map_err(|e| e.to_string())?;
+        let reader: Box<dyn io::Read> = if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+            Box::new(flate2::read::GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries().map_err(|e| e.to_string())? {
+            let mut entry = entry.map_err(|e| e.to_string())?;
+            let path = entry.path().map_err(|e| e.to_string())?.into_owned();
+            let Some(file_name) = path.file_name().map(|n| n.to_owned()) else {
+                continue;
+            };
+            if entry.header().entry_type().is_file() && file_name.to_string_lossy().ends_with(".onnx") {
+                let target = staging.join(file_name);
+                io::copy(&mut entry, &mut File::create(&target).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+                extracted.push(target);
+            }
+        }
+    }
+    Ok(extracted)
+}
+
+fn install(source: &str, name: Option<&str>, dir: Option<&str>, force: bool) -> Result<(), String> {
+    let source = Path::new(source);
+    if !source.is_file() {
+        return Err(format!("'{}' does not exist", source.display()));
+    }
+    let target_dir = match dir {
+        Some(dir) => PathBuf::from(dir),
+        None => default_install_dir()?,
+    };
+    fs::create_dir_all(&target_dir).map_err(|e| format!("Failed to create '{}': {}", target_dir.display(), e))?;
+
+    let staging = target_dir.join(".install");
+    fs::create_dir_all(&staging).map_err(|e| e.to_string())?;
+    let result = (|| {
+        let files = if source.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("onnx")) {
+            let file_name = match name {
+                Some(name) => known_files().into_iter()
+                    .find(|(known, _)| known.eq_ignore_ascii_case(name))
+                    .map(|(_, file_name)| file_name)
+                    .ok_or_else(|| format!("Unknown model name '{}'. See `image_converter models list`.", name))?,
+                None => source.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            };
+            let staged = staging.join(file_name);
+            fs::copy(source, &staged).map_err(|e| e.to_string())?;
+            vec![staged]
+        } else {
+            extract_archive(source, &staging)?
+        };
+        if files.is_empty() {
+            return Err(format!("No .onnx files found in '{}'", source.display()));
+        }
+        for staged in files {
+            let file_name = staged.file_name().unwrap_or_default().to_owned();
+            let target = target_dir.join(&file_name);
+            if target.exists() && !force {
+                return Err(format!("'{}' already exists (use --force to overwrite)", target.display()));
+            }
+            match check_integrity(&staged, MANIFEST).map_err(|e| e.to_string())? {
+                Integrity::Mismatch { expected, actual } if !force => {
+                    return Err(format!("Checksum mismatch for {}: expected {} got {} (use --force to install anyway)", file_name.to_string_lossy(), expected, actual));
+                }
+                Integrity::Unknown(actual) if is_known_file(&staged) => {
+                    eprintln!("{} {} {}", "[WARNING]".bold().yellow(), "⚠".yellow(), format!("{}: no checksum in the manifest, so it cannot be verified (sha256 {})", file_name.to_string_lossy(), actual));
+                }
+                _ => {}
+            }
+            fs::rename(&staged, &target).or_else(|_| fs::copy(&staged, &target).map(|_| ()))
+                .map_err(|e| format!("Failed to install '{}': {}", target.display(), e))?;
+            println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("Installed {}", target.display()));
+        }
+        Ok(())
+    })();
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+pub fn run(command: ModelsCommand) -> bool {
+    match command {
+        ModelsCommand::List => {
+            list();
+            true
+        }
+        ModelsCommand::Paths => {
+            paths();
+            true
+        }
+        ModelsCommand::Verify { models } => verify(&models),
+        ModelsCommand::Install { source, name, dir, force } => match install(&source, name.as_deref(), dir.as_deref(), force) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), e);
+                false
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("model-manager-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, b"not really a model").unwrap();
+        path
+    }
+
+    fn manifest_entries() -> Vec<(&'static str, &'static str)> {
+        MANIFEST.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once(char::is_whitespace))
+            .map(|(hash, file)| (hash, file.trim()))
+            .collect()
+    }
+
+    #[test]
+    fn known_model_without_an_entry_fails() {
+        let path = temp_file("u2net.onnx");
+        assert!(!verify_file(&path, ""));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn custom_model_without_an_entry_passes() {
+        let path = temp_file("my-model.onnx");
+        assert!(verify_file(&path, MANIFEST));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn checksum_mismatch_fails() {
+        let path = temp_file("u2netp.onnx");
+        let manifest = format!("{}  u2netp.onnx\n", "0".repeat(64));
+        assert!(matches!(check_integrity(&path, &manifest).unwrap(), Integrity::Mismatch { expected, .. } if expected == "0".repeat(64)));
+        assert!(!verify_file(&path, &manifest));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn matching_checksum_passes() {
+        let path = temp_file("silueta.onnx");
+        let manifest = format!("# comment\n{}  silueta.onnx\n", sha256_file(&path).unwrap().to_uppercase());
+        assert!(matches!(check_integrity(&path, &manifest).unwrap(), Integrity::Verified));
+        assert!(verify_file(&path, &manifest));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn manifest_entries_are_well_formed() {
+        let entries = manifest_entries();
+        for (i, (hash, file)) in entries.iter().enumerate() {
+            assert_eq!(hash.len(), 64, "{}", file);
+            assert!(hash.chars().all(|c| c.is_ascii_hexdigit()), "{}", file);
+            assert!(is_known_file(Path::new(file)), "{} is not a known model file", file);
+            assert!(entries[..i].iter().all(|(_, other)| other != file), "{} is listed twice", file);
+        }
+    }
+
+    #[test]
+    #[ignore = "assets/models.sha256 has no published checksums yet"]
+    fn every_known_file_has_a_manifest_entry() {
+        let missing: Vec<String> = known_files().into_iter()
+            .map(|(_, file_name)| file_name)
+            .filter(|file_name| expected_checksum(MANIFEST, file_name).is_none())
+            .collect();
+        assert!(missing.is_empty(), "no checksum for {:?}", missing);
+    }
+}
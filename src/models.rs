@@ -0,0 +1,195 @@
+use clap::ValueEnum;
+use std::env;
+use std::path::{Path, PathBuf};
+
+pub const MODEL_ENV: &str = "IMAGE_CONVERTER_MODEL";
+pub const MODEL_DIR_ENV: &str = "IMAGE_CONVERTER_MODEL_DIR";
+
+const IMAGENET_MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+const IMAGENET_STD: [f32; 3] = [0.229, 0.224, 0.225];
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+#[clap(rename_all = "kebab-case")]
+pub enum ModelKind {
+    U2net,
+    U2netp,
+    Silueta,
+    IsnetGeneralUse,
+    Birefnet,
+}
+
+#[derive(Debug)]
+pub struct OutputSpec {
+    pub index: usize,
+    pub sigmoid: bool,
+    pub min_max: bool,
+}
+
+const SIGMOIDED_OUTPUT: OutputSpec = OutputSpec { index: 0, sigmoid: false, min_max: true };
+const LOGIT_OUTPUT: OutputSpec = OutputSpec { index: 0, sigmoid: true, min_max: true };
+
+#[derive(Debug)]
+pub struct ModelSpec {
+    pub kind: ModelKind,
+    pub name: &'static str,
+    pub file_name: &'static str,
+    pub input_size: u32,
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+    pub output: OutputSpec,
+}
+
+pub const REGISTRY: [ModelSpec; 5] = [
+    ModelSpec {
+        kind: ModelKind::U2net,
+        name: "u2net",
+        file_name: "u2net.onnx",
+        input_size: 320,
+        mean: IMAGENET_MEAN,
+        std: IMAGENET_STD,
+        output: SIGMOIDED_OUTPUT,
+    },
+    ModelSpec {
+        kind: ModelKind::U2netp,
+        name: "u2netp",
+        file_name: "u2netp.onnx",
+        input_size: 320,
+        mean: IMAGENET_MEAN,
+        std: IMAGENET_STD,
+        output: SIGMOIDED_OUTPUT,
+    },
+    ModelSpec {
+        kind: ModelKind::Silueta,
+        name: "silueta",
+        file_name: "silueta.onnx",
+        input_size: 320,
+        mean: IMAGENET_MEAN,
+        std: IMAGENET_STD,
+        output: SIGMOIDED_OUTPUT,
+    },
+    ModelSpec {
+        kind: ModelKind::IsnetGeneralUse,
+        name: "isnet-general-use",
+        file_name: "isnet-general-use.onnx",
+        input_size: 1024,
+        mean: [0.5, 0.5, 0.5],
+        std: [1.0, 1.0, 1.0],
+        output: SIGMOIDED_OUTPUT,
+    },
+    ModelSpec {
+        kind: ModelKind::Birefnet,
+        name: "birefnet",
+        file_name: "birefnet.onnx",
+        input_size: 1024,
+        mean: IMAGENET_MEAN,
+        std: IMAGENET_STD,
+        output: LOGIT_OUTPUT,
+    },
+];
+
+impl ModelKind {
+    pub fn spec(&self) -> &'static ModelSpec {
+        REGISTRY.iter().find(|spec| spec.kind == *self).expect("every model kind has a registry entry")
+    }
+}
+
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct ModelOptions {
+    #[arg(long, value_name = "NAME|PATH", help = "Background-removal model: a known model name (see --model-type) or a path to an .onnx file. Falls back to $IMAGE_CONVERTER_MODEL, then u2net")]
+    pub model: Option<String>,
+    #[arg(long, value_enum, value_name = "KIND", help = "Model family of --model, used for input size and normalization (guessed from the file name if omitted)")]
+    pub model_type: Option<ModelKind>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedModel {
+    pub spec: &'static ModelSpec,
+    pub path: PathBuf,
+}
+
+fn user_data_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_DATA_HOME").filter(|v| !v.is_empty()) {
+        Some(home) => Some(PathBuf::from(home)),
+        None => env::var_os("HOME").filter(|v| !v.is_empty()).map(|home| PathBuf::from(home).join(".local").join("share")),
+    }
+}
+
+pub fn user_model_dir() -> Option<PathBuf> {
+    user_data_dir().map(|dir| dir.join("image_converter").join("models"))
+}
+
+fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = user_data_dir().into_iter().collect();
+    let system = env::var("XDG_DATA_DIRS").ok().filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(system.split(':').filter(|d| !d.is_empty()).map(PathBuf::from));
+    dirs
+}
+
+pub fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    if let Some(dir) = env::var_os(MODEL_DIR_ENV).filter(|v| !v.is_empty()) {
+        dirs.push(PathBuf::from(dir));
+    }
+    dirs.push(PathBuf::from("models"));
+    for data_dir in data_dirs() {
+        dirs.push(data_dir.join("image_converter").join("models"));
+    }
+    if let Some(exe_dir) = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+        dirs.push(exe_dir.join("models"));
+        dirs.push(exe_dir);
+    }
+    dirs
+}
+
+pub fn find_file(name: &str, file_name: &str) -> Result<PathBuf, String> {
+    let dirs = search_dirs();
+    dirs.iter()
+        .map(|dir| dir.join(file_name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            let searched: Vec<String> = dirs.iter().map(|d| format!("  {}", d.join(file_name).display())).collect();
+            format!("Model '{}' not found. Searched:\n{}", name, searched.join("\n"))
+        })
+}
+
+/// Int8-quantized variants sit next to the full models as `<name>-int8.onnx`.
+pub fn quantized_file_name(file_name: &str) -> String {
+    format!("{}-int8.onnx", file_name.trim_end_matches(".onnx"))
+}
+
+pub fn find_model_file(spec: &ModelSpec, quantized: bool) -> Result<PathBuf, String> {
+    let file_name = if quantized { quantized_file_name(spec.file_name) } else { spec.file_name.to_string() };
+    find_file(spec.name, &file_name)
+        .map_err(|e| format!("{}\nUse --model <path> or set {} to point at the .onnx file.", e, MODEL_ENV))
+}
+
+fn guess_kind(path: &Path) -> Option<ModelKind> {
+    let stem = path.file_stem()?.to_str()?.to_lowercase();
+    let mut candidates: Vec<&ModelSpec> = REGISTRY.iter().filter(|spec| stem.contains(spec.name)).collect();
+    candidates.sort_by_key(|spec| std::cmp::Reverse(spec.name.len()));
+    candidates.first().map(|spec| spec.kind)
+}
+
+pub fn resolve(options: &ModelOptions, quantized: bool) -> Result<ResolvedModel, String> {
+    let requested = options.model.clone()
+        .or_else(|| env::var(MODEL_ENV).ok().filter(|v| !v.trim().is_empty()));
+    match requested {
+        Some(value) => {
+            if let Ok(kind) = ModelKind::from_str(&value, true) {
+                let spec = kind.spec();
+                return Ok(ResolvedModel { spec, path: find_model_file(spec, quantized)? });
+            }
+            let path = PathBuf::from(&value);
+            if !path.is_file() {
+                return Err(format!("Model file not found at {:?}.", path));
+            }
+            let kind = options.model_type.or_else(|| guess_kind(&path)).unwrap_or(ModelKind::U2net);
+            Ok(ResolvedModel { spec: kind.spec(), path })
+        }
+        None => {
+            let spec = options.model_type.unwrap_or(ModelKind::U2net).spec();
+            Ok(ResolvedModel { spec, path: find_model_file(spec, quantized)? })
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use image::{imageops, DynamicImage, GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
+use ndarray::{s, Array3, Array4, Axis};
+use onnxruntime::{session::Session, tensor::OrtOwnedTensor};
+use palette::{FromColor, Lab, Srgb};
+use colored::*;
+use std::path::PathBuf;
+use std::time::Instant;
+use crate::{inference::{self, OnnxOptions}, models};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputKind {
+    /// Three RGB planes scaled to 0-1, then normalized with mean/std.
+    Rgb,
+    /// The CIELAB L plane (0-100), normalized with mean[0]/std[0].
+    Lightness,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputKind {
+    /// Three RGB planes in 0-1.
+    Rgb,
+    /// CIELAB a/b planes, recombined with the lightness of the input.
+    Chroma,
+}
+
+#[derive(Debug)]
+pub struct ImageModelDescriptor {
+    pub name: &'static str,
+    pub file_name: &'static str,
+    pub input: InputKind,
+    pub input_size: Option<u32>,
+    pub tile: Option<u32>,
+    pub tile_pad: u32,
+    pub scale: u32,
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+    pub output: OutputKind,
+}
+
+pub const IMAGE_MODELS: [ImageModelDescriptor; 3] = [
+    ImageModelDescriptor {
+        name: "realesrgan-x2",
+        file_name: "realesrgan-x2.onnx",
+        input: InputKind::Rgb,
+        input_size: None,
+        tile: Some(256),
+        tile_pad: 16,
+        scale: 2,
+        mean: [0.0, 0.0, 0.0],
+        std: [1.0, 1.0, 1.0],
+        output: OutputKind::Rgb,
+    },
+    ImageModelDescriptor {
+        name: "realesrgan-x4",
+        file_name: "realesrgan-x4.onnx",
+        input: InputKind::Rgb,
+        input_size: None,
+        tile: Some(256),
+        tile_pad: 16,
+        scale: 4,
+        mean: [0.0, 0.0, 0.0],
+        std: [1.0, 1.0, 1.0],
+        output: OutputKind::Rgb,
+    },
+    ImageModelDescriptor {
+        name: "colorizer",
+        file_name: "colorizer.onnx",
+        input: InputKind::Lightness,
+        input_size: Some(256),
+        tile: None,
+        tile_pad: 0,
+        scale: 1,
+        mean: [0.0, 0.0, 0.0],
+        std: [1.0, 1.0, 1.0],
+        output: OutputKind::Chroma,
+    },
+];
+
+pub fn descriptor(name: &str) -> &'static ImageModelDescriptor {
+    IMAGE_MODELS.iter().find(|d| d.name == name).expect("image model is registered")
+}
+
+fn lab_image(img: &RgbImage) -> Vec<Lab> {
+    img.pixels()
+        .map(|p| Lab::from_color(Srgb::new(p[0], p[1], p[2]).into_format::<f32>()))
+        .collect()
+}
+
+/// `imageops::resize` clamps f32 samples to 0-1, so the plane is mapped onto that range and back.
+fn resize_plane(plane: ndarray::ArrayView2<f32>, width: u32, height: u32) -> ndarray::Array2<f32> {
+    let (rows, cols) = plane.dim();
+    let min = plane.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = plane.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let range = if max > min { max - min } else { 1.0 };
+    let buffer = ImageBuffer::<Luma<f32>, Vec<f32>>::from_fn(cols as u32, rows as u32, |x, y| Luma([(plane[[y as usize, x as usize]] - min) / range]));
+    let resized = imageops::resize(&buffer, width, height, imageops::FilterType::CatmullRom);
+    ndarray::Array2::from_shape_fn((height as usize, width as usize), |(y, x)| resized.get_pixel(x as u32, y as u32)[0] * range + min)
+}
+
+/// Runs one input tensor through an image model. The loaded sessions implement it, and in batch
+/// mode so does a worker's handle to the thread that owns them.
+pub trait ImageModelRunner {
+    fn run(&mut self, descriptor: &'static ImageModelDescriptor, input: Array4<f32>) -> Result<Array4<f32>, String>;
+}
+
+pub struct OnnxImageModel {
+    pub descriptor: &'static ImageModelDescriptor,
+    session: Session<'static>,
+}
+
+impl OnnxImageModel {
+    pub fn load(descriptor: &'static ImageModelDescriptor, path: Option<&str>, options: &OnnxOptions) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None if options.quantized => models::find_file(descriptor.name, &models::quantized_file_name(descriptor.file_name))?,
+            None => models::find_file(descriptor.name, descriptor.file_name)?,
+        };
+        println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Loading ONNX model '{}' from {}", descriptor.name, path.display()));
+        let session = inference::load_session(path.clone(), options)?;
+        let channels = match descriptor.input {
+            InputKind::Rgb => 3,
+            InputKind::Lightness => 1,
+        };
+        let dims = session.inputs.first().map(|input| input.dimensions.clone()).unwrap_or_default();
+        if dims.len() != 4 || dims[1].is_some_and(|c| c != channels) {
+            return Err(format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Model '{}' takes input {:?}, but '{}' expects {} channels in NCHW layout.", path.display(), dims, descriptor.name, channels)));
+        }
+        Ok(OnnxImageModel { descriptor, session })
+    }
+
+    fn run(&mut self, input: Array4<f32>) -> Result<Array4<f32>, String> {
+        let outputs: Vec<OrtOwnedTensor<f32, _>> = self.session.run(vec![input])
+            .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("ONNX inference failed: {}", e)))?;
+        outputs[0].view().to_owned()
+            .into_dimensionality::<ndarray::Ix4>()
+            .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to convert ONNX output: {}", e)))
+    }
+}
+
+/// The models an `EnhanceOptions` asks for, each loaded once and reused for every image.
+#[derive(Default)]
+pub struct ImageModels {
+    models: Vec<OnnxImageModel>,
+}
+
+impl ImageModelRunner for ImageModels {
+    fn run(&mut self, descriptor: &'static ImageModelDescriptor, input: Array4<f32>) -> Result<Array4<f32>, String> {
+        self.models.iter_mut()
+            .find(|model| model.descriptor.name == descriptor.name)
+            .ok_or_else(|| format!("Model '{}' is not loaded", descriptor.name))?
+            .run(input)
+    }
+}
+
+fn input_tensor(d: &ImageModelDescriptor, img: &RgbImage) -> Array4<f32> {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    match d.input {
+        InputKind::Rgb => {
+            let mut array = Array4::<f32>::zeros((1, 3, height, width));
+            for (x, y, pixel) in img.enumerate_pixels() {
+                for c in 0..3 {
+                    array[[0, c, y as usize, x as usize]] = (pixel[c] as f32 / 255.0 - d.mean[c]) / d.std[c];
+                }
+            }
+            array
+        }
+        InputKind::Lightness => {
+            let lab = lab_image(img);
+            Array4::from_shape_fn((1, 1, height, width), |(_, _, y, x)| (lab[y * width + x].l - d.mean[0]) / d.std[0])
+        }
+    }
+}
+
+/// Runs the model once and returns its output planes at the target size of `img`.
+fn infer_whole(d: &'static ImageModelDescriptor, runner: &mut impl ImageModelRunner, img: &RgbImage) -> Result<Array3<f32>, String> {
+    let (width, height) = (img.width() * d.scale, img.height() * d.scale);
+    let input = match d.input_size {
+        Some(size) => input_tensor(d, &imageops::resize(img, size, size, imageops::FilterType::Triangle)),
+        None => input_tensor(d, img),
+    };
+    let output = runner.run(d, input)?.index_axis_move(Axis(0), 0);
+    if output.dim().1 == height as usize && output.dim().2 == width as usize {
+        return Ok(output);
+    }
+    let planes: Vec<_> = output.outer_iter().map(|plane| resize_plane(plane, width, height)).collect();
+    let views: Vec<_> = planes.iter().map(|plane| plane.view()).collect();
+    ndarray::stack(Axis(0), &views).map_err(|e| e.to_string())
+}
+
+fn infer(d: &'static ImageModelDescriptor, runner: &mut impl ImageModelRunner, img: &RgbImage) -> Result<Array3<f32>, String> {
+    let tile = match (d.input_size, d.tile) {
+        (None, Some(tile)) if img.width().max(img.height()) > tile => tile,
+        _ => return infer_whole(d, runner, img),
+    };
+    let (width, height) = img.dimensions();
+    let factor = d.scale as usize;
+    let mut output: Option<Array3<f32>> = None;
+    for ty in (0..height).step_by(tile as usize) {
+        for tx in (0..width).step_by(tile as usize) {
+            let (tw, th) = (tile.min(width - tx), tile.min(height - ty));
+            let (x0, y0) = (tx.saturating_sub(d.tile_pad), ty.saturating_sub(d.tile_pad));
+            let (x1, y1) = ((tx + tw + d.tile_pad).min(width), (ty + th + d.tile_pad).min(height));
+            let planes = infer_whole(d, runner, &imageops::crop_imm(img, x0, y0, x1 - x0, y1 - y0).to_image())?;
+            let out = output.get_or_insert_with(|| Array3::zeros((planes.dim().0, height as usize * factor, width as usize * factor)));
+            let (ox, oy) = ((tx - x0) as usize * factor, (ty - y0) as usize * factor);
+            let (tx, ty, tw, th) = (tx as usize * factor, ty as usize * factor, tw as usize * factor, th as usize * factor);
+            out.slice_mut(s![.., ty..ty + th, tx..tx + tw])
+                .assign(&planes.slice(s![.., oy..oy + th, ox..ox + tw]));
+        }
+    }
+    output.ok_or_else(|| "Image is empty".to_string())
+}
+
+pub fn process(d: &'static ImageModelDescriptor, runner: &mut impl ImageModelRunner, img: &RgbImage) -> Result<RgbImage, String> {
+    let planes = infer(d, runner, img)?;
+    let channels = planes.dim().0;
+    let (width, height) = (planes.dim().2 as u32, planes.dim().1 as u32);
+    match d.output {
+        OutputKind::Rgb => {
+            if channels < 3 {
+                return Err(format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Model '{}' returned {} channels, expected 3.", d.name, channels)));
+            }
+            Ok(RgbImage::from_fn(width, height, |x, y| {
+                let value = |c: usize| (planes[[c, y as usize, x as usize]].clamp(0.0, 1.0) * 255.0).round() as u8;
+                Rgb([value(0), value(1), value(2)])
+            }))
+        }
+        OutputKind::Chroma => {
+            if channels < 2 {
+                return Err(format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Model '{}' returned {} channels, expected 2.", d.name, channels)));
+            }
+            let lab = lab_image(img);
+            Ok(RgbImage::from_fn(width, height, |x, y| {
+                let (x, y) = (x as usize, y as usize);
+                let l = lab[y * width as usize + x].l;
+                let color = Srgb::from_color(Lab::new(l, planes[[0, y, x]], planes[[1, y, x]])).into_format::<u8>();
+                Rgb([color.red, color.green, color.blue])
+            }))
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Debug)]
+pub enum Upscale {
+    #[value(name = "2x")]
+    X2,
+    #[value(name = "4x")]
+    X4,
+}
+
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct EnhanceOptions {
+    #[arg(long, value_enum, value_name = "FACTOR", help = "Upscale with a local Real-ESRGAN model (realesrgan-x2.onnx or realesrgan-x4.onnx, looked up like --model)")]
+    pub upscale: Option<Upscale>,
+    #[arg(long, value_name = "PATH", requires = "upscale", help = "Path to the .onnx file used by --upscale")]
+    pub upscale_model: Option<String>,
+    #[arg(long, help = "Colorize grayscale photos with a local colorization model (colorizer.onnx)")]
+    pub colorize: bool,
+    #[arg(long, value_name = "PATH", requires = "colorize", help = "Path to the .onnx file used by --colorize")]
+    pub colorize_model: Option<String>,
+}
+
+fn enhance(descriptor: &'static ImageModelDescriptor, runner: &mut impl ImageModelRunner, img: DynamicImage, benchmark: bool) -> Result<DynamicImage, String> {
+    let started = Instant::now();
+    let rgb = process(descriptor, runner, &img.to_rgb8())?;
+    if benchmark {
+        inference::print_latency(1, started.elapsed());
+    }
+    println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("'{}' finished ({}x{}).", descriptor.name, rgb.width(), rgb.height()));
+    if !img.color().has_alpha() {
+        return Ok(DynamicImage::ImageRgb8(rgb));
+    }
+    let rgba = img.to_rgba8();
+    let alpha: GrayImage = ImageBuffer::from_fn(img.width(), img.height(), |x, y| Luma([rgba.get_pixel(x, y)[3]]));
+    let alpha = imageops::resize(&alpha, rgb.width(), rgb.height(), imageops::FilterType::Lanczos3);
+    Ok(DynamicImage::ImageRgba8(ImageBuffer::from_fn(rgb.width(), rgb.height(), |x, y| {
+        let p = rgb.get_pixel(x, y);
+        image::Rgba([p[0], p[1], p[2], alpha.get_pixel(x, y)[0]])
+    })))
+}
+
+impl EnhanceOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.upscale.is_some() || self.colorize
+    }
+
+    /// The models to run, in order, with their --*-model path if one was given.
+    fn models(&self) -> Vec<(&'static ImageModelDescriptor, Option<&str>)> {
+        let mut models = vec![];
+        if self.colorize {
+            models.push((descriptor("colorizer"), self.colorize_model.as_deref()));
+        }
+        if let Some(factor) = self.upscale {
+            let name = match factor {
+                Upscale::X2 => "realesrgan-x2",
+                Upscale::X4 => "realesrgan-x4",
+            };
+            models.push((descriptor(name), self.upscale_model.as_deref()));
+        }
+        models
+    }
+
+    /// Loads every model these options need; nothing when enhancement is off.
+    pub fn load(&self, onnx: &OnnxOptions) -> Result<ImageModels, String> {
+        let models = self.models().into_iter()
+            .map(|(descriptor, path)| OnnxImageModel::load(descriptor, path, onnx))
+            .collect::<Result<_, _>>()?;
+        Ok(ImageModels { models })
+    }
+
+    pub fn apply(&self, mut img: DynamicImage, runner: &mut impl ImageModelRunner, benchmark: bool) -> Result<DynamicImage, String> {
+        for (descriptor, _) in self.models() {
+            img = enhance(descriptor, runner, img, benchmark)?;
+        }
+        Ok(img)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    const TILED: ImageModelDescriptor = ImageModelDescriptor {
+        name: "test-x2",
+        file_name: "test-x2.onnx",
+        input: InputKind::Rgb,
+        input_size: None,
+        tile: Some(8),
+        tile_pad: 2,
+        scale: 2,
+        mean: [0.0, 0.0, 0.0],
+        std: [1.0, 1.0, 1.0],
+        output: OutputKind::Rgb,
+    };
+    const WHOLE: ImageModelDescriptor = ImageModelDescriptor { tile: None, ..TILED };
+    const UNPADDED: ImageModelDescriptor = ImageModelDescriptor { tile_pad: 0, ..TILED };
+    const CHROMA: ImageModelDescriptor = ImageModelDescriptor {
+        name: "test-chroma",
+        file_name: "test-chroma.onnx",
+        input: InputKind::Lightness,
+        input_size: Some(8),
+        tile: None,
+        tile_pad: 0,
+        scale: 1,
+        mean: [0.0, 0.0, 0.0],
+        std: [1.0, 1.0, 1.0],
+        output: OutputKind::Chroma,
+    };
+
+    /// A 3x3 box blur followed by a nearest x2 upscale, so tiles only match the whole image
+    /// when their padding covers the blur. Records the input shapes it was given.
+    struct BlurUpscale {
+        inputs: Vec<(usize, usize)>,
+    }
+
+    impl ImageModelRunner for BlurUpscale {
+        fn run(&mut self, _: &'static ImageModelDescriptor, input: Array4<f32>) -> Result<Array4<f32>, String> {
+            let (_, channels, height, width) = input.dim();
+            self.inputs.push((height, width));
+            Ok(Array4::from_shape_fn((1, channels, height * 2, width * 2), |(_, c, y, x)| {
+                let (y, x) = (y / 2, x / 2);
+                let mut sum = 0.0;
+                for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                    for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                        sum += input[[0, c, ny, nx]];
+                    }
+                }
+                sum / 9.0
+            }))
+        }
+    }
+
+    /// Answers every input with the a/b planes (20, -30), checking it is given a lightness plane.
+    struct ConstantChroma;
+
+    impl ImageModelRunner for ConstantChroma {
+        fn run(&mut self, _: &'static ImageModelDescriptor, input: Array4<f32>) -> Result<Array4<f32>, String> {
+            let (_, channels, height, width) = input.dim();
+            assert_eq!(channels, 1);
+            assert!(input.iter().all(|l| (0.0..=100.0).contains(l)));
+            Ok(Array4::from_shape_fn((1, 2, height, width), |(_, c, _, _)| if c == 0 { 20.0 } else { -30.0 }))
+        }
+    }
+
+    fn gradient(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| Rgb([(x * 12) as u8, (y * 19) as u8, ((x * y * 7) % 256) as u8]))
+    }
+
+    fn assert_chroma(pixel: &Rgb<u8>, l: f32) {
+        let expected = Srgb::from_color(Lab::new(l, 20.0, -30.0)).into_format::<u8>();
+        for (got, want) in pixel.0.iter().zip([expected.red, expected.green, expected.blue]) {
+            assert!(got.abs_diff(want) <= 1, "{:?} is not Lab({}, 20, -30)", pixel, l);
+        }
+    }
+
+    #[test]
+    fn tiles_stitch_into_the_whole_image_result() {
+        let img = gradient(20, 13);
+        let whole = process(&WHOLE, &mut BlurUpscale { inputs: vec![] }, &img).unwrap();
+        let mut runner = BlurUpscale { inputs: vec![] };
+        let tiled = process(&TILED, &mut runner, &img).unwrap();
+        assert_eq!(tiled.dimensions(), (40, 26));
+        assert!(tiled == whole);
+        // Tiles start every 8 pixels and reach 2 pixels past each side, clipped to the image.
+        assert_eq!(runner.inputs, vec![(10, 10), (10, 12), (10, 6), (7, 10), (7, 12), (7, 6)]);
+    }
+
+    #[test]
+    fn tiles_without_padding_show_seams() {
+        let img = gradient(20, 13);
+        let whole = process(&WHOLE, &mut BlurUpscale { inputs: vec![] }, &img).unwrap();
+        let tiled = process(&UNPADDED, &mut BlurUpscale { inputs: vec![] }, &img).unwrap();
+        assert!(tiled != whole);
+    }
+
+    #[test]
+    fn chroma_output_keeps_the_input_lightness() {
+        let img = RgbImage::from_fn(5, 4, |x, y| {
+            let v = (80 + x * 30 + y * 10) as u8;
+            Rgb([v, v, v])
+        });
+        let out = process(&CHROMA, &mut ConstantChroma, &img).unwrap();
+        assert_eq!(out.dimensions(), (5, 4));
+        let lab = lab_image(&img);
+        for (x, y, pixel) in out.enumerate_pixels() {
+            assert_chroma(pixel, lab[(y * 5 + x) as usize].l);
+        }
+    }
+
+    #[test]
+    fn unloaded_models_are_an_error() {
+        let input = Array4::zeros((1, 3, 4, 4));
+        assert!(ImageModels::default().run(descriptor("realesrgan-x2"), input).is_err());
+    }
+
+    fn fixture(descriptor: &'static ImageModelDescriptor, name: &str) -> ImageModels {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/onnx").join(name);
+        let model = OnnxImageModel::load(descriptor, path.to_str(), &OnnxOptions::default()).unwrap();
+        ImageModels { models: vec![model] }
+    }
+
+    /// nearest-x2.onnx is a single Resize (nearest, asymmetric, floor) by 2; the image is wide
+    /// enough to be split into 256 pixel tiles.
+    #[test]
+    fn upscaler_fixture_stitches_tiles() {
+        let img = gradient(300, 20);
+        let mut models = fixture(descriptor("realesrgan-x2"), "nearest-x2.onnx");
+        let out = process(descriptor("realesrgan-x2"), &mut models, &img).unwrap();
+        assert_eq!(out.dimensions(), (600, 40));
+        for (x, y, pixel) in out.enumerate_pixels() {
+            assert_eq!(pixel, img.get_pixel(x / 2, y / 2));
+        }
+    }
+
+    /// constant-chroma.onnx ignores its lightness input and returns a = 20, b = -30 everywhere.
+    #[test]
+    fn colorizer_fixture_recombines_chroma() {
+        let img = RgbImage::from_fn(16, 16, |x, y| {
+            let v = (60 + x * 6 + y * 5) as u8;
+            Rgb([v, v, v])
+        });
+        let mut models = fixture(descriptor("colorizer"), "constant-chroma.onnx");
+        let out = process(descriptor("colorizer"), &mut models, &img).unwrap();
+        let lab = lab_image(&img);
+        for (x, y, pixel) in out.enumerate_pixels() {
+            assert_chroma(pixel, lab[(y * 16 + x) as usize].l);
+        }
+    }
+}
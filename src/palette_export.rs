@@ -0,0 +1,225 @@
+use image::{Rgb, RgbImage};
+use palette::{FromColor, Hsl, Lab, Srgb};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::palette_extract::PaletteColor;
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Debug)]
+#[clap(rename_all = "lower")]
+pub enum PaletteFormat {
+    Json,
+    Css,
+    Scss,
+    Tailwind,
+    Gpl,
+    Ase,
+    Svg,
+    Png,
+}
+
+impl PaletteFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PaletteFormat::Json => "json",
+            PaletteFormat::Css => "css",
+            PaletteFormat::Scss => "scss",
+            PaletteFormat::Tailwind => "tailwind.js",
+            PaletteFormat::Gpl => "gpl",
+            PaletteFormat::Ase => "ase",
+            PaletteFormat::Svg => "svg",
+            PaletteFormat::Png => "png",
+        }
+    }
+
+    fn from_path(path: &str) -> Option<PaletteFormat> {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".tailwind.js") || lower.ends_with("tailwind.config.js") {
+            return Some(PaletteFormat::Tailwind);
+        }
+        match Path::new(&lower).extension()?.to_str()? {
+            "json" => Some(PaletteFormat::Json),
+            "css" => Some(PaletteFormat::Css),
+            "scss" => Some(PaletteFormat::Scss),
+            "js" => Some(PaletteFormat::Tailwind),
+            "gpl" => Some(PaletteFormat::Gpl),
+            "ase" => Some(PaletteFormat::Ase),
+            "svg" => Some(PaletteFormat::Svg),
+            "png" => Some(PaletteFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct PaletteExport {
+    #[arg(long, value_enum, value_name = "FORMAT", help = "Also write the palette to a file in this format")]
+    pub palette_format: Option<PaletteFormat>,
+    #[arg(long, value_name = "FILE", help = "Palette file to write (format taken from the extension unless --palette-format is given; defaults to <image>-palette.<ext>)")]
+    pub palette_out: Option<String>,
+}
+
+impl PaletteExport {
+    pub fn is_enabled(&self) -> bool {
+        self.palette_format.is_some() || self.palette_out.is_some()
+    }
+
+    /// Resolves the output file and format, returning None when no export was requested.
+    pub fn target(&self, image_path: &str) -> Result<Option<(PathBuf, PaletteFormat)>, String> {
+        let format = match (self.palette_format, &self.palette_out) {
+            (Some(format), _) => format,
+            (None, Some(out)) => PaletteFormat::from_path(out)
+                .ok_or_else(|| format!("Can't tell the palette format from '{}'; pass --palette-format", out))?,
+            (None, None) => return Ok(None),
+        };
+        let path = match &self.palette_out {
+            Some(out) => PathBuf::from(out),
+            None => {
+                let stem = Path::new(image_path).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "palette".to_string());
+                Path::new(image_path).with_file_name(format!("{}-palette.{}", stem, format.extension()))
+            }
+        };
+        Ok(Some((path, format)))
+    }
+}
+
+fn parse_hex(hex: &str) -> [u8; 3] {
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2).unwrap_or("00"), 16).unwrap_or(0);
+    [channel(1), channel(3), channel(5)]
+}
+
+#[derive(Serialize)]
+struct HslValue {
+    h: f32,
+    s: f32,
+    l: f32,
+}
+
+#[derive(Serialize)]
+struct LabValue {
+    l: f32,
+    a: f32,
+    b: f32,
+}
+
+#[derive(Serialize)]
+struct ColorEntry {
+    hex: String,
+    rgb: [u8; 3],
+    hsl: HslValue,
+    lab: LabValue,
+    coverage_percent: f32,
+}
+
+fn round(value: f32, places: i32) -> f32 {
+    let factor = 10f32.powi(places);
+    (value * factor).round() / factor
+}
+
+fn color_entry(color: &PaletteColor) -> ColorEntry {
+    let rgb = color.rgb();
+    let srgb = Srgb::new(rgb[0], rgb[1], rgb[2]).into_format::<f32>();
+    let hsl = Hsl::from_color(srgb);
+    let lab = Lab::from_color(srgb);
+    ColorEntry {
+        hex: color.hex.clone(),
+        rgb,
+        hsl: HslValue {
+            h: round(hsl.hue.into_positive_degrees(), 1),
+            s: round(hsl.saturation * 100.0, 1),
+            l: round(hsl.lightness * 100.0, 1),
+        },
+        lab: LabValue { l: round(lab.l, 2), a: round(lab.a, 2), b: round(lab.b, 2) },
+        coverage_percent: round(color.share * 100.0, 2),
+    }
+}
+
+fn to_json(name: &str, palette: &[PaletteColor]) -> Result<Vec<u8>, String> {
+    #[derive(Serialize)]
+    struct Document<'a> {
+        name: &'a str,
+        colors: Vec<ColorEntry>,
+    }
+    let document = Document { name, colors: palette.iter().map(color_entry).collect() };
+    serde_json::to_vec_pretty(&document).map_err(|e| e.to_string())
+}
+
+fn to_css(palette: &[String]) -> String {
+    let vars: Vec<String> = palette.iter().enumerate().map(|(i, hex)| format!("  --color-{}: {};", i + 1, hex)).collect();
+    format!(":root {{\n{}\n}}\n", vars.join("\n"))
+}
+
+fn to_scss(palette: &[String]) -> String {
+    palette.iter().enumerate().map(|(i, hex)| format!("$color-{}: {};\n", i + 1, hex)).collect()
+}
+
+fn to_tailwind(palette: &[String]) -> String {
+    let entries: Vec<String> = palette.iter().enumerate().map(|(i, hex)| format!("          {}: '{}',", (i + 1) * 100, hex)).collect();
+    format!("module.exports = {{\n  theme: {{\n    extend: {{\n      colors: {{\n        palette: {{\n{}\n        }},\n      }},\n    }},\n  }},\n}};\n", entries.join("\n"))
+}
+
+fn to_gpl(name: &str, palette: &[String]) -> String {
+    let mut out = format!("GIMP Palette\nName: {}\nColumns: {}\n#\n", name, palette.len());
+    for hex in palette {
+        let [r, g, b] = parse_hex(hex);
+        out.push_str(&format!("{:>3} {:>3} {:>3}\t{}\n", r, g, b, hex));
+    }
+    out
+}
+
+/// Adobe Swatch Exchange 1.0: one global RGB swatch per color, big-endian.
+fn to_ase(palette: &[String]) -> Vec<u8> {
+    let mut out = b"ASEF".to_vec();
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&(palette.len() as u32).to_be_bytes());
+    for hex in palette {
+        let name: Vec<u16> = hex.encode_utf16().chain(std::iter::once(0)).collect();
+        let mut block = vec![];
+        block.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        for unit in &name {
+            block.extend_from_slice(&unit.to_be_bytes());
+        }
+        block.extend_from_slice(b"RGB ");
+        for channel in parse_hex(hex) {
+            block.extend_from_slice(&(channel as f32 / 255.0).to_be_bytes());
+        }
+        block.extend_from_slice(&0u16.to_be_bytes());
+        out.extend_from_slice(&1u16.to_be_bytes());
+        out.extend_from_slice(&(block.len() as u32).to_be_bytes());
+        out.extend_from_slice(&block);
+    }
+    out
+}
+
+const SWATCH: u32 = 64;
+
+fn to_svg(palette: &[String]) -> String {
+    let rects: Vec<String> = palette.iter().enumerate()
+        .map(|(i, hex)| format!("  <rect x=\"{}\" y=\"0\" width=\"{}\" height=\"{}\" fill=\"{}\"><title>{}</title></rect>", i as u32 * SWATCH, SWATCH, SWATCH, hex, hex))
+        .collect();
+    format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n{}\n</svg>\n",
+        palette.len() as u32 * SWATCH, SWATCH, palette.len() as u32 * SWATCH, SWATCH, rects.join("\n"))
+}
+
+fn to_png(palette: &[String]) -> RgbImage {
+    RgbImage::from_fn((palette.len() as u32 * SWATCH).max(1), SWATCH, |x, _| {
+        Rgb(palette.get((x / SWATCH) as usize).map(|hex| parse_hex(hex)).unwrap_or([0, 0, 0]))
+    })
+}
+
+pub fn write_palette(colors: &[PaletteColor], path: &Path, format: PaletteFormat) -> Result<(), String> {
+    let palette: &[String] = &colors.iter().map(|c| c.hex.clone()).collect::<Vec<_>>();
+    let name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_else(|| "palette".to_string());
+    let result = match format {
+        PaletteFormat::Json => to_json(&name, colors).and_then(|data| fs::write(path, data).map_err(|e| e.to_string())),
+        PaletteFormat::Css => fs::write(path, to_css(palette)).map_err(|e| e.to_string()),
+        PaletteFormat::Scss => fs::write(path, to_scss(palette)).map_err(|e| e.to_string()),
+        PaletteFormat::Tailwind => fs::write(path, to_tailwind(palette)).map_err(|e| e.to_string()),
+        PaletteFormat::Gpl => fs::write(path, to_gpl(&name, palette)).map_err(|e| e.to_string()),
+        PaletteFormat::Ase => fs::write(path, to_ase(palette)).map_err(|e| e.to_string()),
+        PaletteFormat::Svg => fs::write(path, to_svg(palette)).map_err(|e| e.to_string()),
+        PaletteFormat::Png => to_png(palette).save_with_format(path, image::ImageFormat::Png).map_err(|e| e.to_string()),
+    };
+    result.map_err(|e| format!("Failed to write palette '{}': {}", path.display(), e))
+}
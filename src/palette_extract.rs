@@ -1,28 +1,369 @@
 use image::DynamicImage;
-use palette::{Srgb};
-use kmeans_colors::get_kmeans;
+use colored::*;
+use palette::color_difference::Ciede2000;
+use palette::{FromColor, Lab, Srgb};
+use kmeans_colors::{get_kmeans, Calculate};
 
-pub fn extract_palette(img: &DynamicImage, num_colors: usize) -> Vec<String> {
+pub const MAX_COLORS: usize = 32;
+const MAX_ITERATIONS: usize = 20;
+const SEED: u64 = 42;
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Debug)]
+#[clap(rename_all = "kebab-case")]
+pub enum KmeansInit {
+    /// k-means++: spread the starting centroids out by distance
+    PlusPlus,
+    /// Start from randomly picked pixels
+    Random,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct PaletteOptions {
+    #[arg(short = 'c', long, value_name = "N", default_value_t = 6, value_parser = parse_colors, help = "Number of palette colors")]
+    pub colors: usize,
+    #[arg(long, value_enum, value_name = "MODE", default_value = "plus-plus", help = "How k-means picks its starting colors")]
+    pub palette_init: KmeansInit,
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=32), help = "Run k-means N times with different seeds and keep the tightest result")]
+    pub palette_restarts: u32,
+    #[arg(long, value_name = "DELTA_E", default_value_t = 3.0, help = "Merge palette colors closer than this CIEDE2000 distance (0 keeps every cluster)")]
+    pub palette_merge: f32,
+    #[arg(long, help = "Print WCAG 2.x contrast ratios for palette colors, black and white, with AA/AAA results")]
+    pub palette_contrast: bool,
+    #[arg(long, requires = "palette_contrast", help = "Also show APCA lightness contrast (Lc) for each pair")]
+    pub apca: bool,
+}
+
+impl Default for PaletteOptions {
+    fn default() -> Self {
+        PaletteOptions {
+            colors: 6,
+            palette_init: KmeansInit::PlusPlus,
+            palette_restarts: 1,
+            palette_merge: 3.0,
+            palette_contrast: false,
+            apca: false,
+        }
+    }
+}
+
+fn parse_colors(value: &str) -> Result<usize, String> {
+    let colors: usize = value.parse().map_err(|_| format!("'{}' is not a number", value))?;
+    if !(1..=MAX_COLORS).contains(&colors) {
+        return Err(format!("pick between 1 and {} colors", MAX_COLORS));
+    }
+    Ok(colors)
+}
+
+/// A palette entry and the fraction of opaque pixels it covers.
+#[derive(Clone, Debug)]
+pub struct PaletteColor {
+    pub hex: String,
+    pub share: f32,
+}
+
+impl PaletteColor {
+    pub fn rgb(&self) -> [u8; 3] {
+        hex_to_rgb(&self.hex)
+    }
+}
+
+fn hex_to_rgb(hex: &str) -> [u8; 3] {
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2).unwrap_or("00"), 16).unwrap_or(0);
+    [channel(1), channel(3), channel(5)]
+}
+
+struct Clustering {
+    centroids: Vec<Lab>,
+    indices: Vec<u8>,
+    inertia: f32,
+}
+
+/// Plain Lloyd iterations seeded with pixels picked by a small xorshift generator.
+fn kmeans_random(k: usize, data: &[Lab], seed: u64) -> (Vec<Lab>, Vec<u8>) {
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let mut centroids: Vec<Lab> = (0..k).map(|_| data[(next() % data.len() as u64) as usize]).collect();
+    let mut indices = Vec::with_capacity(data.len());
+    for _ in 0..MAX_ITERATIONS {
+        indices.clear();
+        Lab::get_closest_centroid(data, &centroids, &mut indices);
+        let mut sums = vec![(0.0f32, 0.0f32, 0.0f32, 0usize); k];
+        for (color, &index) in data.iter().zip(&indices) {
+            let sum = &mut sums[index as usize];
+            sum.0 += color.l;
+            sum.1 += color.a;
+            sum.2 += color.b;
+            sum.3 += 1;
+        }
+        let updated: Vec<Lab> = sums.iter().zip(&centroids)
+            .map(|(&(l, a, b, n), &old)| if n == 0 { old } else { Lab::new(l / n as f32, a / n as f32, b / n as f32) })
+            .collect();
+        let moved = Lab::check_loop(&updated, &centroids);
+        centroids = updated;
+        if moved <= 1e-3 {
+            break;
+        }
+    }
+    indices.clear();
+    Lab::get_closest_centroid(data, &centroids, &mut indices);
+    (centroids, indices)
+}
+
+fn cluster(data: &[Lab], k: usize, init: KmeansInit, seed: u64) -> Clustering {
+    let (centroids, indices) = match init {
+        KmeansInit::PlusPlus => {
+            let result = get_kmeans(k, MAX_ITERATIONS, 1e-3, false, data, seed);
+            (result.centroids, result.indices)
+        }
+        KmeansInit::Random => kmeans_random(k, data, seed),
+    };
+    let inertia = data.iter().zip(&indices).map(|(color, &i)| <Lab as Calculate>::difference(color, &centroids[i as usize])).sum();
+    Clustering { centroids, indices, inertia }
+}
+
+/// Repeatedly folds the two closest centroids into their pixel-weighted mean until
+/// every pair is at least `threshold` apart.
+fn merge_similar(mut clusters: Vec<(Lab, usize)>, threshold: f32) -> Vec<(Lab, usize)> {
+    loop {
+        let mut closest: Option<(usize, usize, f32)> = None;
+        for i in 0..clusters.len() {
+            for j in i + 1..clusters.len() {
+                let distance = Ciede2000::difference(clusters[i].0, clusters[j].0);
+                if distance < threshold && closest.is_none_or(|(_, _, best)| distance < best) {
+                    closest = Some((i, j, distance));
+                }
+            }
+        }
+        let Some((i, j, _)) = closest else {
+            return clusters;
+        };
+        let (b, nb) = clusters.remove(j);
+        let (a, na) = clusters[i];
+        let (wa, wb) = (na as f32, nb as f32);
+        let total = (wa + wb).max(1.0);
+        clusters[i] = (Lab::new((a.l * wa + b.l * wb) / total, (a.a * wa + b.a * wb) / total, (a.b * wa + b.b * wb) / total), na + nb);
+    }
+}
+
+/// Downscales `img` to at most 128 px and returns its opaque pixels in CIELAB.
+pub fn sample_pixels(img: &DynamicImage) -> Vec<Lab> {
     let small = if img.width() > 128 || img.height() > 128 {
         img.resize(128, 128, image::imageops::FilterType::Triangle)
     } else {
         img.clone()
     };
-    let rgb_img = small.to_rgb8();
-    let pixels: Vec<Srgb<u8>> = rgb_img.pixels().map(|p| Srgb::new(p[0], p[1], p[2])).collect();
-    let data: Vec<Srgb<f32>> = pixels.iter().map(|&c| c.into_format::<f32>()).collect();
-    let result = get_kmeans(
-        20,
-        num_colors,
-        1e-3,
-        false,
-        &data,
-        42,
-    );
-    let mut colors = vec![];
-    for center in result.centroids {
-        let color = center.into_format::<u8>();
-        colors.push(format!("#{:02X}{:02X}{:02X}", color.red, color.green, color.blue));
-    }
-    colors
-}
\ No newline at end of file
+    let rgba_img = small.to_rgba8();
+    rgba_img.pixels()
+        .filter(|p| p[3] > 0)
+        .map(|p| Lab::from_color(Srgb::new(p[0], p[1], p[2]).into_format::<f32>()))
+        .collect()
+}
+
+/// Clusters CIELAB samples and returns the palette sorted by coverage, most dominant first.
+/// Returns an empty palette when there are no samples.
+pub fn cluster_palette(data: &[Lab], options: &PaletteOptions) -> Vec<PaletteColor> {
+    if data.is_empty() {
+        return vec![];
+    }
+    let k = options.colors.min(data.len());
+    let best = (0..options.palette_restarts.max(1) as u64)
+        .map(|run| cluster(data, k, options.palette_init, SEED + run))
+        .min_by(|a, b| a.inertia.total_cmp(&b.inertia))
+        .expect("at least one k-means run");
+
+    let mut counts = vec![0usize; best.centroids.len()];
+    for &index in &best.indices {
+        counts[index as usize] += 1;
+    }
+    let clusters: Vec<(Lab, usize)> = best.centroids.into_iter().zip(counts).filter(|&(_, n)| n > 0).collect();
+    let mut clusters = if options.palette_merge > 0.0 { merge_similar(clusters, options.palette_merge) } else { clusters };
+    clusters.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+
+    clusters.into_iter().map(|(center, count)| {
+        let color = Srgb::from_color(center).into_format::<u8>();
+        PaletteColor {
+            hex: format!("#{:02X}{:02X}{:02X}", color.red, color.green, color.blue),
+            share: count as f32 / data.len() as f32,
+        }
+    }).collect()
+}
+
+/// Clusters the opaque pixels of `img` in CIELAB and returns the palette sorted by coverage,
+/// most dominant first. Returns an empty palette when the image has no opaque pixels.
+pub fn extract_palette(img: &DynamicImage, options: &PaletteOptions) -> Vec<PaletteColor> {
+    cluster_palette(&sample_pixels(img), options)
+}
+
+pub fn palette_from_file(path: &str, options: &PaletteOptions) -> Result<Vec<PaletteColor>, String> {
+    let img = image::open(path)
+        .map_err(|e| format!("Failed to open image '{}' for palette extraction: {}", path, e))?;
+    let palette = extract_palette(&img, options);
+    if palette.is_empty() {
+        return Err(format!("'{}' has no opaque pixels to extract a palette from", path));
+    }
+    Ok(palette)
+}
+
+pub fn print_palette(palette: &[PaletteColor]) {
+    println!("\n{}", "Dominant Color Palette:".magenta().bold());
+    for color in palette {
+        let [r, g, b] = color.rgb();
+        println!("  {}  {}  {:>5.1}%", "    ".on_truecolor(r, g, b), color.hex.bold(), color.share * 100.0);
+    }
+    println!();
+}
+
+fn linearize(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// WCAG 2.x relative luminance.
+fn relative_luminance([r, g, b]: [u8; 3]) -> f32 {
+    0.2126 * linearize(r) + 0.7152 * linearize(g) + 0.0722 * linearize(b)
+}
+
+pub fn wcag_contrast(a: [u8; 3], b: [u8; 3]) -> f32 {
+    let (la, lb) = (relative_luminance(a), relative_luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+/// APCA 0.0.98G lightness contrast of `text` on `background`; negative for light text on dark.
+pub fn apca_contrast(text: [u8; 3], background: [u8; 3]) -> f32 {
+    let screen_luminance = |[r, g, b]: [u8; 3]| {
+        let y = 0.2126729 * (r as f32 / 255.0).powf(2.4) + 0.7151522 * (g as f32 / 255.0).powf(2.4) + 0.0721750 * (b as f32 / 255.0).powf(2.4);
+        if y < 0.022 { y + (0.022 - y).powf(1.414) } else { y }
+    };
+    let (txt, bg) = (screen_luminance(text), screen_luminance(background));
+    if (bg - txt).abs() < 0.0005 {
+        return 0.0;
+    }
+    if bg > txt {
+        let s = (bg.powf(0.56) - txt.powf(0.57)) * 1.14;
+        if s < 0.1 { 0.0 } else { (s - 0.027) * 100.0 }
+    } else {
+        let s = (bg.powf(0.65) - txt.powf(0.62)) * 1.14;
+        if s > -0.1 { 0.0 } else { (s + 0.027) * 100.0 }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum WcagLevel {
+    Fail,
+    AaLarge,
+    Aa,
+    Aaa,
+}
+
+impl WcagLevel {
+    /// Normal-size text thresholds; large text (18pt or 14pt bold) passes AA at 3:1 and AAA at 4.5:1.
+    pub fn from_ratio(ratio: f32) -> WcagLevel {
+        if ratio >= 7.0 {
+            WcagLevel::Aaa
+        } else if ratio >= 4.5 {
+            WcagLevel::Aa
+        } else if ratio >= 3.0 {
+            WcagLevel::AaLarge
+        } else {
+            WcagLevel::Fail
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WcagLevel::Aaa => "AAA",
+            WcagLevel::Aa => "AA",
+            WcagLevel::AaLarge => "AA large",
+            WcagLevel::Fail => "fail",
+        }
+    }
+}
+
+/// A text/background pairing; the darker color is the text on the lighter background.
+pub struct ContrastPair {
+    pub text: String,
+    pub background: String,
+    pub ratio: f32,
+    pub level: WcagLevel,
+    /// APCA Lc for `text` on `background` (dark on light).
+    pub apca: f32,
+    /// APCA Lc with the roles swapped (light on dark). APCA is polarity dependent, unlike WCAG.
+    pub apca_reversed: f32,
+}
+
+/// Every pairing of palette colors plus black and white, best contrast first.
+pub fn contrast_pairs(palette: &[PaletteColor]) -> Vec<ContrastPair> {
+    let mut candidates: Vec<(String, [u8; 3])> = palette.iter().map(|c| (c.hex.clone(), c.rgb())).collect();
+    for (hex, rgb) in [("#000000", [0, 0, 0]), ("#FFFFFF", [255, 255, 255])] {
+        if !candidates.iter().any(|(existing, _)| existing == hex) {
+            candidates.push((hex.to_string(), rgb));
+        }
+    }
+    let mut pairs = vec![];
+    for i in 0..candidates.len() {
+        for j in i + 1..candidates.len() {
+            let (mut text, mut background) = (&candidates[i], &candidates[j]);
+            if relative_luminance(text.1) > relative_luminance(background.1) {
+                std::mem::swap(&mut text, &mut background);
+            }
+            let ratio = wcag_contrast(text.1, background.1);
+            pairs.push(ContrastPair {
+                text: text.0.clone(),
+                background: background.0.clone(),
+                ratio,
+                level: WcagLevel::from_ratio(ratio),
+                apca: apca_contrast(text.1, background.1),
+                apca_reversed: apca_contrast(background.1, text.1),
+            });
+        }
+    }
+    pairs.sort_by(|a, b| b.ratio.total_cmp(&a.ratio));
+    pairs
+}
+
+fn apca_use(lc: f32) -> &'static str {
+    match lc.abs() {
+        lc if lc >= 90.0 => "body text (preferred)",
+        lc if lc >= 75.0 => "body text",
+        lc if lc >= 60.0 => "content text",
+        lc if lc >= 45.0 => "headlines",
+        lc if lc >= 30.0 => "non-text only",
+        _ => "not readable",
+    }
+}
+
+/// Prints the pairings that pass at least WCAG AA for large text, plus a count of the rest.
+pub fn print_contrast_report(palette: &[PaletteColor], apca: bool) {
+    let pairs = contrast_pairs(palette);
+    println!("{}", "Readable Text / Background Pairs (WCAG 2.x):".magenta().bold());
+    let mut failing = 0;
+    for pair in &pairs {
+        if pair.level == WcagLevel::Fail {
+            failing += 1;
+            continue;
+        }
+        let [tr, tg, tb] = hex_to_rgb(&pair.text);
+        let [br, bg, bb] = hex_to_rgb(&pair.background);
+        let sample = " Aa ".truecolor(tr, tg, tb).on_truecolor(br, bg, bb);
+        let level = match pair.level {
+            WcagLevel::Aaa => pair.level.label().green().bold(),
+            WcagLevel::Aa => pair.level.label().green(),
+            _ => pair.level.label().yellow(),
+        };
+        print!("  {}  {} on {}  {:>5.2}:1  {:<8}", sample, pair.text, pair.background, pair.ratio, level);
+        if apca {
+            let reversed = " Aa ".truecolor(br, bg, bb).on_truecolor(tr, tg, tb);
+            print!("  Lc {:>6.1} ({})  {} reversed Lc {:>6.1} ({})", pair.apca, apca_use(pair.apca), reversed, pair.apca_reversed, apca_use(pair.apca_reversed));
+        }
+        println!();
+    }
+    if failing > 0 {
+        println!("  {} more pair(s) fail WCAG AA even for large text.", failing);
+    }
+    println!();
+}
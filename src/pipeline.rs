@@ -0,0 +1,363 @@
+use colored::*;
+use image::{DynamicImage, ImageFormat, RgbImage, Rgba, RgbaImage};
+use std::fs;
+use std::path::Path;
+use crate::adjust;
+use crate::bg_replace::{self, Background};
+use crate::{inference::MaskPredictor, apply_mask::apply_mask};
+use crate::tiling::{self, SegmentOptions};
+use crate::models::ModelOptions;
+use crate::mask_refine::MaskOptions;
+use crate::mask_export::{self, MaskExport};
+use crate::effects::CutoutEffects;
+use crate::onnx_image::{EnhanceOptions, ImageModelRunner};
+use crate::inference::OnnxOptions;
+use crate::remap::{self, RemapOptions};
+use ndarray::Array2;
+use crate::filters::{self, Denoise, UnsharpMask};
+use crate::trim;
+use crate::watermark::{self, Anchor};
+
+pub fn parse_hex_color(value: &str) -> Result<Rgba<u8>, String> {
+    let hex = value.trim().trim_start_matches('#');
+    if !hex.is_ascii() {
+        return Err(format!("'{}' is not a valid hex color (e.g. #FFFFFF)", value));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16)
+        .map_err(|_| format!("'{}' is not a valid hex color (e.g. #FFFFFF)", value));
+    match hex.len() {
+        6 => Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, 255])),
+        8 => Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, channel(6)?])),
+        _ => Err(format!("'{}' is not a valid hex color (use #RRGGBB or #RRGGBBAA)", value)),
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ImageOps {
+    #[command(flatten)]
+    pub model: ModelOptions,
+    #[command(flatten)]
+    pub mask: MaskOptions,
+    #[command(flatten)]
+    pub segment: SegmentOptions,
+    #[command(flatten)]
+    pub export: MaskExport,
+    #[command(flatten)]
+    pub effects: CutoutEffects,
+    #[command(flatten)]
+    pub enhance: EnhanceOptions,
+    #[command(flatten)]
+    pub onnx: OnnxOptions,
+    #[command(flatten)]
+    pub remap: RemapOptions,
+    #[arg(long, value_name = "HEX|IMAGE", value_parser = bg_replace::parse_background, requires = "remove_bg", conflicts_with = "bg_blur", help = "Put the cut-out subject on a new background: a color (#RRGGBB) or an image (scaled to cover)")]
+    pub bg_replace: Option<Background>,
+    #[arg(long, value_name = "SIGMA", value_parser = filters::parse_sigma, requires = "remove_bg", help = "Put the cut-out subject on a blurred copy of the original (portrait mode)")]
+    pub bg_blur: Option<f32>,
+    #[arg(long, help = "Trim uniform-color or fully transparent borders")]
+    pub trim: bool,
+    #[arg(long, value_name = "PERCENT", default_value = "0%", value_parser = trim::parse_percent, requires = "trim", help = "Color tolerance used by --trim, e.g. 5%")]
+    pub fuzz: f32,
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "trim", help = "Margin in pixels to keep around the trimmed content")]
+    pub trim_padding: u32,
+    #[arg(long, value_name = "PERCENT", allow_negative_numbers = true, value_parser = parse_adjustment, help = "Brightness adjustment from -100 to 100")]
+    pub brightness: Option<f32>,
+    #[arg(long, value_name = "PERCENT", allow_negative_numbers = true, value_parser = parse_adjustment, help = "Contrast adjustment from -100 to 100")]
+    pub contrast: Option<f32>,
+    #[arg(long, value_name = "PERCENT", allow_negative_numbers = true, value_parser = parse_adjustment, help = "Saturation adjustment from -100 (removes all color) to 100")]
+    pub saturation: Option<f32>,
+    #[arg(long, value_name = "GAMMA", value_parser = parse_gamma, help = "Gamma correction, values above 1 brighten midtones")]
+    pub gamma: Option<f32>,
+    #[arg(long, help = "Convert the image to grayscale")]
+    pub grayscale: bool,
+    #[arg(long, help = "Apply a sepia tone")]
+    pub sepia: bool,
+    #[arg(long, help = "Invert the colors")]
+    pub invert: bool,
+    #[arg(long, help = "Stretch each color channel to the full range (ignores transparent pixels)")]
+    pub auto_level: bool,
+    #[arg(long, value_enum, value_name = "METHOD", help = "Reduce noise with a median or bilateral filter")]
+    pub denoise: Option<Denoise>,
+    #[arg(long, value_name = "PX", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=32), requires = "denoise", help = "Neighbourhood radius used by --denoise (1-32)")]
+    pub denoise_radius: u32,
+    #[arg(long, value_name = "SIGMA", value_parser = filters::parse_sigma, help = "Gaussian blur, e.g. 8 for privacy previews")]
+    pub blur: Option<f32>,
+    #[arg(long, value_name = "RADIUS,AMOUNT,THRESHOLD", value_parser = filters::parse_unsharp, help = "Unsharp mask, e.g. 1.5,0.8,3 (amount and threshold are optional)")]
+    pub sharpen: Option<UnsharpMask>,
+    #[arg(long, value_name = "PNG", help = "Logo/watermark image (PNG with alpha) to composite onto the output")]
+    pub watermark: Option<String>,
+    #[arg(long, value_enum, value_name = "POSITION", default_value = "bottom-right", help = "Corner (or center) to place the watermark at")]
+    pub watermark_position: Anchor,
+    #[arg(long, value_name = "FRACTION", default_value_t = 0.25, value_parser = watermark::parse_scale, help = "Watermark width as a fraction of the image width")]
+    pub watermark_scale: f32,
+    #[arg(long, value_name = "PX", default_value_t = 16, help = "Distance in pixels between the watermark and the image edge (or between tiles)")]
+    pub watermark_margin: u32,
+    #[arg(long, value_name = "0-1", default_value_t = 0.5, value_parser = watermark::parse_opacity, help = "Watermark opacity from 0 (invisible) to 1 (opaque)")]
+    pub watermark_opacity: f32,
+    #[arg(long, requires = "watermark", help = "Tile the watermark across the whole image instead of placing it once")]
+    pub watermark_tile: bool,
+    #[arg(long, value_name = "TEXT", help = "Text to draw onto the output image")]
+    pub text: Option<String>,
+    #[arg(long, value_name = "TTF", requires = "text", help = "TTF/OTF font for --text (defaults to the bundled DejaVu Sans)")]
+    pub font: Option<String>,
+    #[arg(long, value_name = "PX", default_value_t = 32.0, value_parser = watermark::parse_text_size, help = "Text height in pixels")]
+    pub text_size: f32,
+    #[arg(long, value_name = "HEX", default_value = "#FFFFFF", value_parser = parse_hex_color, help = "Text color as #RRGGBB or #RRGGBBAA")]
+    pub text_color: Rgba<u8>,
+    #[arg(long, value_enum, value_name = "POSITION", default_value = "bottom-left", help = "Corner (or center) to place the text at")]
+    pub text_position: Anchor,
+    #[arg(long, value_name = "PX", default_value_t = 16, help = "Distance in pixels between the text and the image edge")]
+    pub text_margin: u32,
+}
+
+impl Default for ImageOps {
+    fn default() -> Self {
+        ImageOps {
+            model: ModelOptions::default(),
+            mask: MaskOptions::default(),
+            segment: SegmentOptions::default(),
+            export: MaskExport::default(),
+            effects: CutoutEffects::default(),
+            enhance: EnhanceOptions::default(),
+            onnx: OnnxOptions::default(),
+            remap: RemapOptions::default(),
+            bg_replace: None,
+            bg_blur: None,
+            trim: false,
+            fuzz: 0.0,
+            trim_padding: 0,
+            brightness: None,
+            contrast: None,
+            saturation: None,
+            gamma: None,
+            grayscale: false,
+            sepia: false,
+            invert: false,
+            auto_level: false,
+            denoise: None,
+            denoise_radius: 1,
+            blur: None,
+            sharpen: None,
+            watermark: None,
+            watermark_position: Anchor::BottomRight,
+            watermark_scale: 0.25,
+            watermark_margin: 16,
+            watermark_opacity: 0.5,
+            watermark_tile: false,
+            text: None,
+            font: None,
+            text_size: 32.0,
+            text_color: Rgba([255, 255, 255, 255]),
+            text_position: Anchor::BottomLeft,
+            text_margin: 16,
+        }
+    }
+}
+
+fn parse_adjustment(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(percent) if (-100.0..=100.0).contains(&percent) => Ok(percent),
+        _ => Err(format!("'{}' is not a valid adjustment (must be between -100 and 100)", value)),
+    }
+}
+
+fn parse_gamma(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(gamma) if gamma > 0.0 => Ok(gamma),
+        _ => Err(format!("'{}' is not a valid gamma (must be a number above 0)", value)),
+    }
+}
+
+impl ImageOps {
+    pub fn replaces_background(&self) -> bool {
+        self.bg_replace.is_some() || self.bg_blur.is_some()
+    }
+
+    pub fn needs_alpha_output(&self) -> bool {
+        !self.replaces_background() && !self.export.mask_only
+    }
+
+    fn finish_cutout(&self, cutout: RgbaImage) -> Result<DynamicImage, String> {
+        let composited = match (&self.bg_replace, self.bg_blur) {
+            (Some(background), _) => bg_replace::replace_background(&cutout, background)?,
+            (None, Some(sigma)) => bg_replace::blur_background(&cutout, sigma),
+            (None, None) => return Ok(DynamicImage::ImageRgba8(cutout)),
+        };
+        Ok(DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(composited).to_rgb8()))
+    }
+
+    fn has_adjustments(&self) -> bool {
+        self.brightness.is_some() || self.contrast.is_some() || self.saturation.is_some() || self.gamma.is_some()
+            || self.grayscale || self.sepia || self.invert || self.auto_level
+    }
+
+    fn has_filters(&self) -> bool {
+        self.denoise.is_some() || self.blur.is_some() || self.sharpen.is_some()
+    }
+
+    fn has_overlays(&self) -> bool {
+        self.watermark.is_some() || self.text.is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.trim && !self.enhance.is_enabled() && !self.has_adjustments() && !self.has_filters() && !self.has_overlays() && !self.remap.is_enabled()
+    }
+
+    fn apply_adjustments(&self, canvas: &mut image::RgbaImage) {
+        if let Some(percent) = self.brightness {
+            adjust::brightness(canvas, percent);
+        }
+        if let Some(percent) = self.contrast {
+            adjust::contrast(canvas, percent);
+        }
+        if let Some(percent) = self.saturation {
+            adjust::saturation(canvas, percent);
+        }
+        if let Some(gamma) = self.gamma {
+            adjust::gamma(canvas, gamma);
+        }
+        if self.grayscale {
+            adjust::grayscale(canvas);
+        }
+        if self.sepia {
+            adjust::sepia(canvas);
+        }
+        if self.invert {
+            adjust::invert(canvas);
+        }
+        if self.auto_level {
+            adjust::auto_level(canvas);
+        }
+    }
+
+    pub fn apply(&self, mut img: DynamicImage, models: &mut impl ImageModelRunner) -> Result<DynamicImage, String> {
+        if self.trim {
+            img = trim::trim_borders(&img, self.fuzz, self.trim_padding);
+        }
+        if self.enhance.is_enabled() {
+            img = self.enhance.apply(img, models, self.onnx.benchmark)?;
+        }
+        if self.has_adjustments() || self.has_filters() || self.has_overlays() {
+            let had_alpha = img.color().has_alpha();
+            let mut canvas = img.to_rgba8();
+            if let Some(method) = self.denoise {
+                canvas = filters::denoise(&canvas, method, self.denoise_radius);
+            }
+            self.apply_adjustments(&mut canvas);
+            if let Some(sigma) = self.blur {
+                canvas = filters::blur(&canvas, sigma);
+            }
+            if let Some(mask) = self.sharpen {
+                canvas = filters::sharpen(&canvas, mask);
+            }
+            if let Some(path) = &self.watermark {
+                watermark::apply_watermark(&mut canvas, path, self.watermark_position, self.watermark_scale, self.watermark_margin, self.watermark_opacity, self.watermark_tile)?;
+            }
+            if let Some(text) = &self.text {
+                watermark::draw_text(&mut canvas, text, self.font.as_deref(), self.text_size, self.text_color, self.text_position, self.text_margin)?;
+            }
+            img = DynamicImage::ImageRgba8(canvas);
+            if !had_alpha {
+                img = DynamicImage::ImageRgb8(img.to_rgb8());
+            }
+        }
+        if self.remap.is_enabled() {
+            img = self.remap.apply(img);
+        }
+        Ok(img)
+    }
+
+    /// Saves a processed image, as an indexed PNG when it was remapped to a palette.
+    fn save(&self, img: &DynamicImage, output_path: &str, output_ext: &str) -> Result<(), String> {
+        if self.remap.is_enabled() && output_ext == "png" {
+            match remap::save_indexed_png(img, output_path) {
+                Ok(true) => return Ok(()),
+                Ok(false) => println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), "More than 256 color/alpha combinations after remapping; saving a regular PNG."),
+                Err(e) => return Err(format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to save '{}': {}", output_path, e))),
+            }
+        }
+        save_image(img, output_path, output_ext)
+    }
+}
+
+fn flatten_on_white(img: &DynamicImage) -> RgbImage {
+    let rgba = img.to_rgba8();
+    let (w, h) = rgba.dimensions();
+    let mut out = RgbImage::new(w, h);
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        let image::Rgba([r, g, b, a]) = *pixel;
+        let alpha = a as f32 / 255.0;
+        let white = 255.0;
+        let r = (alpha * r as f32 + (1.0 - alpha) * white).round() as u8;
+        let g = (alpha * g as f32 + (1.0 - alpha) * white).round() as u8;
+        let b = (alpha * b as f32 + (1.0 - alpha) * white).round() as u8;
+        out.put_pixel(x, y, image::Rgb([r, g, b]));
+    }
+    out
+}
+
+pub fn save_image(img: &DynamicImage, output_path: &str, output_ext: &str) -> Result<(), String> {
+    let result = match output_ext {
+        "jpg" | "jpeg" => DynamicImage::ImageRgb8(flatten_on_white(img))
+            .save_with_format(output_path, ImageFormat::Jpeg)
+            .map_err(|e| e.to_string()),
+        "png" => img.save_with_format(output_path, ImageFormat::Png)
+            .map_err(|e| e.to_string()),
+        "gif" => DynamicImage::ImageRgba8(img.to_rgba8()).save_with_format(output_path, ImageFormat::Gif)
+            .map_err(|e| e.to_string()),
+        "webp" => {
+            let webp_data = if img.color().has_alpha() {
+                let rgba = img.to_rgba8();
+                webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height()).encode_lossless()
+            } else {
+                let rgb = img.to_rgb8();
+                webp::Encoder::from_rgb(&rgb, rgb.width(), rgb.height()).encode_lossless()
+            };
+            fs::write(output_path, &*webp_data).map_err(|e| e.to_string())
+        }
+        _ => Err(format!("Unsupported output format: {}", output_ext)),
+    };
+    result.map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to save '{}': {}", output_path, e)))
+}
+
+pub fn apply_operations(source_path: &str, output_path: &str, output_ext: &str, ops: &ImageOps, models: &mut impl ImageModelRunner) -> Result<(), String> {
+    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Applying image operations: {} -> {}", source_path, output_path));
+    let img = image::open(source_path)
+        .map_err(|e| format!("[ERROR] Failed to open image '{}': {}", source_path, e))?;
+    let img = ops.apply(img, models)?;
+    ops.save(&img, output_path, output_ext)?;
+    println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("Image operations applied: {}", output_path));
+    Ok(())
+}
+
+/// `relative_dir` is where the output sits below the batch output directory (empty for single
+/// files); directory targets of --mask-out and --mask-report mirror it.
+pub fn finish_background_removal(input_path: &str, mask: Array2<f32>, output_path: &str, relative_dir: &Path, output_ext: &str, ops: &ImageOps, models: &mut impl ImageModelRunner) -> Result<(), String> {
+    let cutout = apply_mask(input_path, mask, &ops.mask).map_err(|e| e.to_string())?;
+    let alpha = mask_export::alpha_channel(&cutout);
+    let report = mask_export::subject_report(input_path, &alpha);
+    match report.bbox {
+        Some(bbox) => println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Subject at {}x{}+{}+{}, mask coverage {:.1}%", bbox.width, bbox.height, bbox.x, bbox.y, report.coverage_percent)),
+        None => println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), "No subject found in the mask."),
+    }
+    ops.export.write_mask(&alpha, output_path, relative_dir)?;
+    ops.export.write_report(&report, output_path, relative_dir)?;
+
+    let crop = |img: DynamicImage| if ops.export.crop_subject {
+        mask_export::crop_to_subject(img, report.bbox, ops.export.crop_margin)
+    } else {
+        img
+    };
+    if ops.export.mask_only {
+        return save_image(&crop(DynamicImage::ImageLuma8(alpha)), output_path, output_ext);
+    }
+    let cutout = ops.effects.apply(crop(DynamicImage::ImageRgba8(cutout)).to_rgba8());
+    let img = ops.apply(ops.finish_cutout(cutout)?, models)?;
+    ops.save(&img, output_path, output_ext)
+}
+
+pub fn remove_background(input_path: &str, output_path: &str, relative_dir: &Path, output_ext: &str, ops: &ImageOps, remover: &mut impl MaskPredictor, models: &mut impl ImageModelRunner) -> Result<(), String> {
+    let mask = tiling::predict_mask(input_path, remover, &ops.segment)
+        .map_err(|e| e.to_string())?;
+    finish_background_removal(input_path, mask, output_path, relative_dir, output_ext, ops, models)
+}
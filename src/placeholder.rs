@@ -0,0 +1,252 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use colored::*;
+use image::{DynamicImage, RgbaImage};
+use image::imageops::FilterType;
+use serde::Serialize;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::palette_extract::{self, PaletteOptions};
+
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct PlaceholderOptions {
+    #[arg(long, help = "Compute BlurHash, ThumbHash, a tiny base64 LQIP and the dominant/average color for each output image")]
+    pub placeholders: bool,
+    #[arg(long, value_name = "PX", default_value_t = 16, value_parser = clap::value_parser!(u32).range(4..=64), requires = "placeholders", help = "Width of the LQIP WebP embedded as a data URI")]
+    pub lqip_width: u32,
+}
+
+impl Default for PlaceholderOptions {
+    fn default() -> Self {
+        PlaceholderOptions { placeholders: false, lqip_width: 16 }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Placeholders {
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+    pub blurhash: String,
+    pub thumbhash: String,
+    pub lqip: String,
+    pub dominant_color: String,
+    pub average_color: String,
+}
+
+fn hex(rgb: [u8; 3]) -> String {
+    format!("#{:02X}{:02X}{:02X}", rgb[0], rgb[1], rgb[2])
+}
+
+/// Mean color of the visible pixels, weighted by alpha.
+fn average_color(img: &RgbaImage) -> [u8; 3] {
+    let mut sum = [0.0f64; 3];
+    let mut weight = 0.0f64;
+    for pixel in img.pixels() {
+        let alpha = pixel[3] as f64 / 255.0;
+        for c in 0..3 {
+            sum[c] += pixel[c] as f64 * alpha;
+        }
+        weight += alpha;
+    }
+    if weight == 0.0 {
+        return [0, 0, 0];
+    }
+    sum.map(|v| (v / weight).round() as u8)
+}
+
+/// DCT of one channel for ThumbHash: the DC term, the AC terms normalised to 0..1 and their scale.
+fn encode_channel(channel: &[f64], w: usize, h: usize, nx: usize, ny: usize) -> (f64, Vec<f64>, f64) {
+    let mut dc = 0.0;
+    let mut ac = vec![];
+    let mut scale = 0.0f64;
+    let mut fx = vec![0.0f64; w];
+    for cy in 0..ny {
+        let mut cx = 0;
+        while cx * ny < nx * (ny - cy) {
+            for (x, f) in fx.iter_mut().enumerate() {
+                *f = (PI / w as f64 * cx as f64 * (x as f64 + 0.5)).cos();
+            }
+            let mut f = 0.0;
+            for y in 0..h {
+                let fy = (PI / h as f64 * cy as f64 * (y as f64 + 0.5)).cos();
+                for x in 0..w {
+                    f += channel[x + y * w] * fx[x] * fy;
+                }
+            }
+            f /= (w * h) as f64;
+            if cx > 0 || cy > 0 {
+                ac.push(f);
+                scale = scale.max(f.abs());
+            } else {
+                dc = f;
+            }
+            cx += 1;
+        }
+    }
+    if scale > 0.0 {
+        for value in ac.iter_mut() {
+            *value = 0.5 + 0.5 / scale * *value;
+        }
+    }
+    (dc, ac, scale)
+}
+
+/// ThumbHash of an image no larger than 100x100, following the reference encoder.
+fn thumbhash(img: &RgbaImage) -> Vec<u8> {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let pixels = img.as_raw();
+    let (mut avg_r, mut avg_g, mut avg_b, mut avg_a) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+    for p in pixels.chunks_exact(4) {
+        let alpha = p[3] as f64 / 255.0;
+        avg_r += alpha / 255.0 * p[0] as f64;
+        avg_g += alpha / 255.0 * p[1] as f64;
+        avg_b += alpha / 255.0 * p[2] as f64;
+        avg_a += alpha;
+    }
+    if avg_a > 0.0 {
+        avg_r /= avg_a;
+        avg_g /= avg_a;
+        avg_b /= avg_a;
+    }
+
+    let has_alpha = avg_a < (w * h) as f64;
+    let l_limit = if has_alpha { 5.0 } else { 7.0 };
+    let longest = w.max(h) as f64;
+    let lx = ((l_limit * w as f64 / longest).round() as usize).max(1);
+    let ly = ((l_limit * h as f64 / longest).round() as usize).max(1);
+
+    // Composite onto the average color and convert to luminance, yellow-blue, red-green and alpha.
+    let mut l = Vec::with_capacity(w * h);
+    let mut p = Vec::with_capacity(w * h);
+    let mut q = Vec::with_capacity(w * h);
+    let mut a = Vec::with_capacity(w * h);
+    for px in pixels.chunks_exact(4) {
+        let alpha = px[3] as f64 / 255.0;
+        let r = avg_r * (1.0 - alpha) + alpha / 255.0 * px[0] as f64;
+        let g = avg_g * (1.0 - alpha) + alpha / 255.0 * px[1] as f64;
+        let b = avg_b * (1.0 - alpha) + alpha / 255.0 * px[2] as f64;
+        l.push((r + g + b) / 3.0);
+        p.push((r + g) / 2.0 - b);
+        q.push(r - g);
+        a.push(alpha);
+    }
+
+    let (l_dc, l_ac, l_scale) = encode_channel(&l, w, h, lx.max(3), ly.max(3));
+    let (p_dc, p_ac, p_scale) = encode_channel(&p, w, h, 3, 3);
+    let (q_dc, q_ac, q_scale) = encode_channel(&q, w, h, 3, 3);
+    let alpha_channel = has_alpha.then(|| encode_channel(&a, w, h, 5, 5));
+
+    let is_landscape = w > h;
+    let header24 = (63.0 * l_dc).round() as u32
+        | ((31.5 + 31.5 * p_dc).round() as u32) << 6
+        | ((31.5 + 31.5 * q_dc).round() as u32) << 12
+        | ((31.0 * l_scale).round() as u32) << 18
+        | (has_alpha as u32) << 23;
+    let header16 = (if is_landscape { ly } else { lx }) as u32
+        | ((63.0 * p_scale).round() as u32) << 3
+        | ((63.0 * q_scale).round() as u32) << 9
+        | (is_landscape as u32) << 15;
+    let mut hash = vec![
+        (header24 & 255) as u8,
+        ((header24 >> 8) & 255) as u8,
+        (header24 >> 16) as u8,
+        (header16 & 255) as u8,
+        (header16 >> 8) as u8,
+    ];
+    let mut channels = vec![l_ac, p_ac, q_ac];
+    if let Some((a_dc, a_ac, a_scale)) = alpha_channel {
+        hash.push((15.0 * a_dc).round() as u8 | ((15.0 * a_scale).round() as u8) << 4);
+        channels.push(a_ac);
+    }
+    let ac_start = hash.len();
+    for (index, value) in channels.iter().flatten().enumerate() {
+        let slot = ac_start + index / 2;
+        if slot == hash.len() {
+            hash.push(0);
+        }
+        hash[slot] |= ((15.0 * value).round() as u8) << ((index & 1) * 4);
+    }
+    hash
+}
+
+fn lqip(img: &DynamicImage, width: u32) -> String {
+    let height = ((img.height() as f32 * width as f32 / img.width().max(1) as f32).round() as u32).max(1);
+    let small = img.resize_exact(width, height, FilterType::Triangle).to_rgba8();
+    let encoded = webp::Encoder::from_rgba(&small, small.width(), small.height()).encode(50.0);
+    format!("data:image/webp;base64,{}", STANDARD.encode(&*encoded))
+}
+
+pub fn compute(path: &str, options: &PlaceholderOptions) -> Result<Placeholders, String> {
+    let img = image::open(path)
+        .map_err(|e| format!("Failed to open image '{}' for placeholders: {}", path, e))?;
+    let (width, height) = (img.width(), img.height());
+    if width == 0 || height == 0 {
+        return Err(format!("'{}' is empty", path));
+    }
+
+    let blurhash_source = img.resize(64, 64, FilterType::Triangle).to_rgba8();
+    let blurhash = blurhash::encode(BLURHASH_COMPONENTS.0, BLURHASH_COMPONENTS.1, blurhash_source.width(), blurhash_source.height(), blurhash_source.as_raw())
+        .map_err(|e| format!("BlurHash failed for '{}': {}", path, e))?;
+    let thumbhash_source = img.resize(100, 100, FilterType::Triangle).to_rgba8();
+    let dominant = palette_extract::extract_palette(&img, &PaletteOptions::default())
+        .first()
+        .map(|c| c.hex.clone())
+        .unwrap_or_else(|| hex([0, 0, 0]));
+
+    Ok(Placeholders {
+        image: path.to_string(),
+        width,
+        height,
+        blurhash,
+        thumbhash: STANDARD.encode(thumbhash(&thumbhash_source)),
+        lqip: lqip(&img, options.lqip_width),
+        dominant_color: dominant,
+        average_color: hex(average_color(&img.to_rgba8())),
+    })
+}
+
+pub fn print_placeholders(placeholders: &Placeholders) {
+    println!("\n{}", "Placeholders:".magenta().bold());
+    println!("  {:<15} {}", "BlurHash", placeholders.blurhash);
+    println!("  {:<15} {}", "ThumbHash", placeholders.thumbhash);
+    println!("  {:<15} {}", "Dominant color", placeholders.dominant_color);
+    println!("  {:<15} {}", "Average color", placeholders.average_color);
+    println!("  {:<15} {}", "LQIP", placeholders.lqip);
+    println!();
+}
+
+/// Batch mode: placeholders for every processed image, written as one JSON manifest.
+#[derive(Clone, Debug)]
+pub struct PlaceholderManifest {
+    pub options: PlaceholderOptions,
+    pub manifest: Option<String>,
+}
+
+impl PlaceholderManifest {
+    /// `processed` holds (input, output) paths; outputs that were not rewritten fall back to the input.
+    pub fn run(&self, processed: &[(String, String)], output_dir: &Path) {
+        let mut entries = vec![];
+        for (input_path, output_path) in processed {
+            let source = if Path::new(output_path).is_file() { output_path } else { input_path };
+            match compute(source, &self.options) {
+                Ok(placeholders) => entries.push(placeholders),
+                Err(e) => eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), e),
+            }
+        }
+        let manifest_path = match &self.manifest {
+            Some(path) => PathBuf::from(path),
+            None => output_dir.join("placeholders.json"),
+        };
+        let written = serde_json::to_vec_pretty(&entries)
+            .map_err(|e| e.to_string())
+            .and_then(|data| fs::write(&manifest_path, data).map_err(|e| e.to_string()));
+        match written {
+            Ok(()) => println!("{} {} {}", "[BATCH]".bold().green(), "✔".green(), format!("Placeholders for {} images written to {}", entries.len(), manifest_path.display())),
+            Err(e) => eprintln!("{} {} {}", "[BATCH]".bold().red(), "✖".red(), format!("Failed to write placeholder manifest '{}': {}", manifest_path.display(), e)),
+        }
+    }
+}
@@ -1,25 +1,126 @@
-use image::{io::Reader as ImageReader};
-use ndarray::Array4;
+use image::{io::Reader as ImageReader, DynamicImage, imageops};
+use ndarray::{s, Array2, Array4};
 use colored::*;
+use crate::models::ModelSpec;
 
-pub fn preprocess_image(path: &str) -> Result<Array4<f32>, Box<dyn std::error::Error>> {
-    println!("{} {} {}", "[INFO]".bold().yellow(), "i".bold().yellow(), format!("Preprocessing image for ONNX model: {}", path));
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Debug)]
+#[clap(rename_all = "lower")]
+pub enum Fit {
+    Letterbox,
+    Stretch,
+}
+
+/// Region of the square model input that holds the image (the rest is padding).
+#[derive(Copy, Clone, Debug)]
+pub struct Placement {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Placement {
+    pub fn crop(&self, mask: Array2<f32>) -> Array2<f32> {
+        if self.x == 0 && self.y == 0 && mask.dim() == (self.height, self.width) {
+            return mask;
+        }
+        mask.slice(s![self.y..self.y + self.height, self.x..self.x + self.width]).to_owned()
+    }
+}
+
+pub fn load_image(path: &str) -> Result<DynamicImage, Box<dyn std::error::Error>> {
     let img = ImageReader::open(path)
         .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to open image '{}': {}", path, e)))?
         .decode()
-        .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to decode image '{}': {}", path, e)))?
-        .resize_exact(320, 320, image::imageops::FilterType::Triangle)
-        .to_rgb8();
+        .map_err(|e| format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to decode image '{}': {}", path, e)))?;
+    Ok(img)
+}
+
+pub fn to_tensor(img: &DynamicImage, spec: &ModelSpec, fit: Fit) -> (Array4<f32>, Placement) {
+    let size = spec.input_size;
+    let (width, height) = match fit {
+        Fit::Stretch => (size, size),
+        Fit::Letterbox => {
+            let scale = size as f32 / img.width().max(img.height()).max(1) as f32;
+            (
+                ((img.width() as f32 * scale).round() as u32).clamp(1, size),
+                ((img.height() as f32 * scale).round() as u32).clamp(1, size),
+            )
+        }
+    };
+    let resized = img.resize_exact(width, height, imageops::FilterType::Triangle).to_rgb8();
+    let placement = Placement {
+        x: ((size - width) / 2) as usize,
+        y: ((size - height) / 2) as usize,
+        width: width as usize,
+        height: height as usize,
+    };
+
+    // Padding stays at zero, which is the mean color once normalized.
+    let mut array = Array4::<f32>::zeros((1, 3, size as usize, size as usize));
+    let max_value = resized.pixels().flat_map(|p| p.0).max().unwrap_or(0).max(1) as f32;
+    for (x, y, pixel) in resized.enumerate_pixels() {
+        for (c, value) in pixel.0.iter().enumerate() {
+            array[[0, c, placement.y + y as usize, placement.x + x as usize]] = (*value as f32 / max_value - spec.mean[c]) / spec.std[c];
+        }
+    }
+    (array, placement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+    use crate::models::{ModelKind, ModelSpec, OutputSpec};
 
-    let mut array = Array4::<f32>::zeros((1, 3, 320, 320));
+    const SPEC: ModelSpec = ModelSpec {
+        kind: ModelKind::U2net,
+        name: "test",
+        file_name: "test.onnx",
+        input_size: 8,
+        mean: [0.5, 0.25, 0.0],
+        std: [0.5, 0.25, 2.0],
+        output: OutputSpec { index: 0, sigmoid: false, min_max: true },
+    };
 
-    for (x, y, pixel) in img.enumerate_pixels() {
-        let [r, g, b] = pixel.0;
-        array[[0, 0, y as usize, x as usize]] = r as f32 / 255.0;
-        array[[0, 1, y as usize, x as usize]] = g as f32 / 255.0;
-        array[[0, 2, y as usize, x as usize]] = b as f32 / 255.0;
+    fn uniform(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([100, 150, 200])))
     }
 
-    println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), "Preprocessing complete.");
-    Ok(array)
-}
\ No newline at end of file
+    #[test]
+    fn normalizes_by_image_max_then_mean_and_std() {
+        let (tensor, placement) = to_tensor(&uniform(8, 8), &SPEC, Fit::Stretch);
+        assert_eq!(tensor.dim(), (1, 3, 8, 8));
+        assert_eq!((placement.x, placement.y, placement.width, placement.height), (0, 0, 8, 8));
+        // The brightest channel value (200) maps to 1.0 before normalization.
+        let expected = [(0.5 - 0.5) / 0.5, (0.75 - 0.25) / 0.25, (1.0 - 0.0) / 2.0];
+        for (c, value) in expected.iter().enumerate() {
+            assert!((tensor[[0, c, 3, 5]] - value).abs() < 1e-6, "channel {}", c);
+        }
+    }
+
+    #[test]
+    fn letterbox_centers_the_image_and_leaves_zero_padding() {
+        let (tensor, placement) = to_tensor(&uniform(4, 2), &SPEC, Fit::Letterbox);
+        assert_eq!((placement.x, placement.y, placement.width, placement.height), (0, 2, 8, 4));
+        for y in 0..8 {
+            let inside = (2..6).contains(&y);
+            assert_eq!(tensor[[0, 1, y, 0]] != 0.0, inside, "row {}", y);
+        }
+
+        let (_, tall) = to_tensor(&uniform(2, 4), &SPEC, Fit::Letterbox);
+        assert_eq!((tall.x, tall.y, tall.width, tall.height), (2, 0, 4, 8));
+    }
+
+    #[test]
+    fn crop_keeps_only_the_placed_region() {
+        let mask = Array2::from_shape_fn((8, 8), |(y, x)| (y * 8 + x) as f32);
+        let cropped = Placement { x: 2, y: 1, width: 3, height: 2 }.crop(mask.clone());
+        assert_eq!(cropped.dim(), (2, 3));
+        assert_eq!(cropped[[0, 0]], 10.0);
+        assert_eq!(cropped[[1, 2]], 20.0);
+
+        let full = Placement { x: 0, y: 0, width: 8, height: 8 }.crop(mask.clone());
+        assert_eq!(full, mask);
+    }
+}
@@ -0,0 +1,232 @@
+use colored::*;
+use image::{DynamicImage, Rgb, RgbaImage};
+use palette::{FromColor, Lab, Srgb};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use crate::palette_extract::{self, PaletteOptions};
+use crate::pipeline::parse_hex_color;
+
+const MAX_PALETTE: usize = 256;
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Debug)]
+#[clap(rename_all = "kebab-case")]
+pub enum Dither {
+    None,
+    FloydSteinberg,
+    Ordered,
+}
+
+/// Where the target palette comes from.
+#[derive(Clone, Debug)]
+pub enum PaletteSource {
+    /// Extract this many colors from the image itself.
+    Extract(usize),
+    Colors(Vec<Rgb<u8>>),
+}
+
+fn parse_hex_list(text: &str) -> Result<Vec<Rgb<u8>>, String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .map(|token| parse_hex_color(token).map(|c| Rgb([c[0], c[1], c[2]])))
+        .collect()
+}
+
+/// GIMP palette: a "GIMP Palette" header, optional Name/Columns lines, then "R G B [name]" rows.
+fn parse_gpl(text: &str) -> Result<Vec<Rgb<u8>>, String> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("GIMP Palette") {
+        return Err("missing 'GIMP Palette' header".to_string());
+    }
+    let mut colors = vec![];
+    for line in lines.map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Name:") || line.starts_with("Columns:") {
+            continue;
+        }
+        let channels: Vec<u8> = line.split_whitespace().take(3).map_while(|v| v.parse().ok()).collect();
+        match channels[..] {
+            [r, g, b] => colors.push(Rgb([r, g, b])),
+            _ => return Err(format!("can't read color line '{}'", line)),
+        }
+    }
+    Ok(colors)
+}
+
+pub fn parse_palette_source(value: &str) -> Result<PaletteSource, String> {
+    if let Some(rest) = value.strip_prefix("auto").filter(|_| !Path::new(value).is_file()) {
+        let colors = match rest.strip_prefix(':') {
+            Some(n) => n.parse().map_err(|_| format!("'{}' is not a color count", n))?,
+            None if rest.is_empty() => 8,
+            None => return Err(format!("'{}' should be auto or auto:N", value)),
+        };
+        if !(1..=palette_extract::MAX_COLORS).contains(&colors) {
+            return Err(format!("pick between 1 and {} colors", palette_extract::MAX_COLORS));
+        }
+        return Ok(PaletteSource::Extract(colors));
+    }
+    let colors = if Path::new(value).is_file() {
+        let text = fs::read_to_string(value).map_err(|e| format!("Failed to read palette '{}': {}", value, e))?;
+        if text.trim_start().starts_with("GIMP Palette") {
+            parse_gpl(&text)
+        } else {
+            parse_hex_list(&text)
+        }
+        .map_err(|e| format!("Invalid palette file '{}': {}", value, e))?
+    } else {
+        parse_hex_list(value)?
+    };
+    match colors.len() {
+        0 => Err(format!("'{}' contains no colors", value)),
+        n if n > MAX_PALETTE => Err(format!("'{}' has {} colors; indexed output allows at most {}", value, n, MAX_PALETTE)),
+        _ => Ok(PaletteSource::Colors(colors)),
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct RemapOptions {
+    #[arg(long, value_name = "PALETTE", value_parser = parse_palette_source, help = "Recolor to a fixed palette: a .gpl file, a file or comma-separated list of hex colors, or auto[:N] to use the image's own N-color palette")]
+    pub remap: Option<PaletteSource>,
+    #[arg(long, value_enum, value_name = "METHOD", default_value = "none", requires = "remap", help = "Dithering used by --remap")]
+    pub dither: Dither,
+}
+
+impl Default for RemapOptions {
+    fn default() -> Self {
+        RemapOptions { remap: None, dither: Dither::None }
+    }
+}
+
+fn to_lab(r: f32, g: f32, b: f32) -> Lab {
+    Lab::from_color(Srgb::new(r / 255.0, g / 255.0, b / 255.0))
+}
+
+fn nearest(palette: &[Lab], color: Lab) -> usize {
+    let distance = |p: &Lab| (p.l - color.l).powi(2) + (p.a - color.a).powi(2) + (p.b - color.b).powi(2);
+    (0..palette.len()).min_by(|&i, &j| distance(&palette[i]).total_cmp(&distance(&palette[j]))).unwrap_or(0)
+}
+
+/// 4x4 Bayer matrix, thresholds in 0..16.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+impl RemapOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.remap.is_some()
+    }
+
+    fn palette_for(&self, img: &DynamicImage) -> Vec<Rgb<u8>> {
+        match &self.remap {
+            Some(PaletteSource::Colors(colors)) => colors.clone(),
+            Some(PaletteSource::Extract(colors)) => palette_extract::extract_palette(img, &PaletteOptions { colors: *colors, ..Default::default() })
+                .iter()
+                .map(|c| Rgb(c.rgb()))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Replaces every visible pixel with its nearest palette color in CIELAB. Alpha is kept as is.
+    pub fn apply(&self, img: DynamicImage) -> DynamicImage {
+        let colors = self.palette_for(&img);
+        if colors.is_empty() {
+            return img;
+        }
+        let had_alpha = img.color().has_alpha();
+        let mut canvas = img.to_rgba8();
+        remap_pixels(&mut canvas, &colors, self.dither);
+        let out = DynamicImage::ImageRgba8(canvas);
+        if had_alpha { out } else { DynamicImage::ImageRgb8(out.to_rgb8()) }
+    }
+}
+
+fn remap_pixels(canvas: &mut RgbaImage, colors: &[Rgb<u8>], dither: Dither) {
+    let labs: Vec<Lab> = colors.iter().map(|c| to_lab(c[0] as f32, c[1] as f32, c[2] as f32)).collect();
+    let (width, height) = canvas.dimensions();
+    match dither {
+        Dither::None => {
+            let mut cache: HashMap<[u8; 3], usize> = HashMap::new();
+            for pixel in canvas.pixels_mut().filter(|p| p[3] > 0) {
+                let key = [pixel[0], pixel[1], pixel[2]];
+                let index = *cache.entry(key).or_insert_with(|| nearest(&labs, to_lab(key[0] as f32, key[1] as f32, key[2] as f32)));
+                let Rgb([r, g, b]) = colors[index];
+                *pixel = image::Rgba([r, g, b, pixel[3]]);
+            }
+        }
+        Dither::Ordered => {
+            // Spread the threshold over roughly one palette step per channel.
+            let spread = 255.0 / (colors.len() as f32).cbrt().max(2.0);
+            for (x, y, pixel) in canvas.enumerate_pixels_mut().filter(|(_, _, p)| p[3] > 0) {
+                let offset = ((BAYER[(y % 4) as usize][(x % 4) as usize] as f32 + 0.5) / 16.0 - 0.5) * spread;
+                let channel = |i: usize| (pixel[i] as f32 + offset).clamp(0.0, 255.0);
+                let Rgb([r, g, b]) = colors[nearest(&labs, to_lab(channel(0), channel(1), channel(2)))];
+                *pixel = image::Rgba([r, g, b, pixel[3]]);
+            }
+        }
+        Dither::FloydSteinberg => {
+            // Error is diffused in sRGB and transparent pixels are left untouched.
+            let (w, h) = (width as usize, height as usize);
+            let mut work: Vec<[f32; 3]> = canvas.pixels().map(|p| [p[0] as f32, p[1] as f32, p[2] as f32]).collect();
+            for y in 0..h {
+                for x in 0..w {
+                    let pixel = canvas.get_pixel_mut(x as u32, y as u32);
+                    if pixel[3] == 0 {
+                        continue;
+                    }
+                    let [r, g, b] = work[y * w + x].map(|v| v.clamp(0.0, 255.0));
+                    let chosen = colors[nearest(&labs, to_lab(r, g, b))];
+                    *pixel = image::Rgba([chosen[0], chosen[1], chosen[2], pixel[3]]);
+                    let error = [r - chosen[0] as f32, g - chosen[1] as f32, b - chosen[2] as f32];
+                    let mut spread = |dx: isize, dy: usize, weight: f32| {
+                        let nx = x as isize + dx;
+                        if nx < 0 || nx >= w as isize || y + dy >= h {
+                            return;
+                        }
+                        let target = &mut work[(y + dy) * w + nx as usize];
+                        for c in 0..3 {
+                            target[c] += error[c] * weight;
+                        }
+                    };
+                    spread(1, 0, 7.0 / 16.0);
+                    spread(-1, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(1, 1, 1.0 / 16.0);
+                }
+            }
+        }
+    }
+}
+
+/// Writes `img` as an 8-bit palette PNG with a tRNS chunk when it uses at most 256 RGBA colors.
+/// Returns false, without writing anything, when the image has too many colors.
+pub fn save_indexed_png(img: &DynamicImage, output_path: &str) -> Result<bool, String> {
+    let rgba = img.to_rgba8();
+    let mut lookup: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut entries: Vec<[u8; 4]> = vec![];
+    let mut indices = Vec::with_capacity((rgba.width() * rgba.height()) as usize);
+    for pixel in rgba.pixels() {
+        let key = if pixel[3] == 0 { [0, 0, 0, 0] } else { pixel.0 };
+        let index = match lookup.get(&key) {
+            Some(&index) => index,
+            None if entries.len() < MAX_PALETTE => {
+                entries.push(key);
+                lookup.insert(key, (entries.len() - 1) as u8);
+                (entries.len() - 1) as u8
+            }
+            None => return Ok(false),
+        };
+        indices.push(index);
+    }
+    let file = File::create(output_path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), rgba.width(), rgba.height());
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(entries.iter().flat_map(|e| [e[0], e[1], e[2]]).collect::<Vec<u8>>());
+    if entries.iter().any(|e| e[3] < 255) {
+        encoder.set_trns(entries.iter().map(|e| e[3]).collect::<Vec<u8>>());
+    }
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(&indices))
+        .map_err(|e| e.to_string())?;
+    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Wrote indexed PNG with {} colors", entries.len()));
+    Ok(true)
+}
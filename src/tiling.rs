@@ -0,0 +1,165 @@
+use image::imageops;
+use ndarray::Array2;
+use colored::*;
+use crate::inference::MaskPredictor;
+use crate::mask_refine::AlphaMask;
+use crate::preprocess::{self, Fit};
+
+const TILES_PER_RUN: usize = 4;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SegmentOptions {
+    #[arg(long, value_enum, value_name = "MODE", default_value = "letterbox", help = "How the image is fitted into the square model input: letterbox keeps the aspect ratio, stretch squashes it like the reference implementation")]
+    pub bg_fit: Fit,
+    #[arg(long, requires = "remove_bg", help = "Also segment overlapping tiles and merge them with the global mask (more detail on large images, more compute)")]
+    pub bg_tiled: bool,
+    #[arg(long, value_name = "PX", default_value_t = 1024, requires = "bg_tiled", help = "Tile size in source pixels used by --bg-tiled")]
+    pub bg_tile_size: u32,
+    #[arg(long, value_name = "FRACTION", default_value_t = 0.25, requires = "bg_tiled", help = "Overlap between neighbouring tiles, from 0 to 0.9")]
+    pub bg_tile_overlap: f32,
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        SegmentOptions {
+            bg_fit: Fit::Letterbox,
+            bg_tiled: false,
+            bg_tile_size: 1024,
+            bg_tile_overlap: 0.25,
+        }
+    }
+}
+
+fn resize_mask(mask: &Array2<f32>, width: u32, height: u32) -> AlphaMask {
+    let (rows, cols) = mask.dim();
+    let buffer = AlphaMask::from_fn(cols as u32, rows as u32, |x, y| image::Luma([mask[[y as usize, x as usize]]]));
+    if buffer.dimensions() == (width, height) {
+        return buffer;
+    }
+    imageops::resize(&buffer, width, height, imageops::FilterType::Triangle)
+}
+
+fn tile_starts(length: u32, tile: u32, stride: u32) -> Vec<u32> {
+    if length <= tile {
+        return vec![0];
+    }
+    let mut starts: Vec<u32> = (0..length - tile).step_by(stride as usize).collect();
+    starts.push(length - tile);
+    starts
+}
+
+/// Weight ramps from 0 to 1 across the overlap, except on sides touching the image border.
+fn edge_weight(pos: u32, start: u32, tile: u32, length: u32, ramp: f32) -> f32 {
+    let offset = (pos - start) as f32;
+    let mut weight = 1.0f32;
+    if start > 0 {
+        weight = weight.min((offset + 1.0) / ramp);
+    }
+    if start + tile < length {
+        weight = weight.min((tile as f32 - offset) / ramp);
+    }
+    weight.clamp(0.0, 1.0)
+}
+
+pub fn predict_mask(path: &str, remover: &mut impl MaskPredictor, options: &SegmentOptions) -> Result<Array2<f32>, Box<dyn std::error::Error>> {
+    let spec = remover.spec();
+    let img = preprocess::load_image(path)?;
+    let (tensor, placement) = preprocess::to_tensor(&img, spec, options.bg_fit);
+    let global = placement.crop(remover.run(tensor)?);
+
+    let (width, height) = (img.width(), img.height());
+    let tile = options.bg_tile_size.max(spec.input_size);
+    if !options.bg_tiled || width.max(height) <= tile {
+        return Ok(global);
+    }
+
+    let tile_w = tile.min(width);
+    let tile_h = tile.min(height);
+    let overlap = options.bg_tile_overlap.clamp(0.0, 0.9);
+    let stride = ((tile as f32 * (1.0 - overlap)) as u32).max(1);
+    let ramp = (tile as f32 * overlap).max(1.0);
+    let tiles: Vec<(u32, u32)> = tile_starts(height, tile_h, stride).into_iter()
+        .flat_map(|y| tile_starts(width, tile_w, stride).into_iter().map(move |x| (x, y)))
+        .collect();
+    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Segmenting {} tiles of {}x{} px...", tiles.len(), tile_w, tile_h));
+
+    let mut sum = vec![0.0f32; (width * height) as usize];
+    let mut weights = vec![0.0f32; (width * height) as usize];
+    for chunk in tiles.chunks(TILES_PER_RUN) {
+        let (tensors, placements): (Vec<_>, Vec<_>) = chunk.iter()
+            .map(|&(x, y)| preprocess::to_tensor(&img.crop_imm(x, y, tile_w, tile_h), spec, options.bg_fit))
+            .unzip();
+        let masks = remover.run_batch(tensors)?;
+        for ((&(x0, y0), placement), mask) in chunk.iter().zip(placements).zip(masks) {
+            let tile_mask = resize_mask(&placement.crop(mask), tile_w, tile_h);
+            for (tx, ty, value) in tile_mask.enumerate_pixels() {
+                let (x, y) = (x0 + tx, y0 + ty);
+                let weight = edge_weight(x, x0, tile_w, width, ramp) * edge_weight(y, y0, tile_h, height, ramp);
+                let index = (y * width + x) as usize;
+                sum[index] += value[0] * weight;
+                weights[index] += weight;
+            }
+        }
+    }
+
+    // Keep the global mask where it is confident and let the tiles decide along uncertain edges,
+    // so a tile that only sees part of the subject cannot punch holes into it.
+    let global = resize_mask(&global, width, height);
+    Ok(Array2::from_shape_fn((height as usize, width as usize), |(y, x)| {
+        let coarse = global.get_pixel(x as u32, y as u32)[0];
+        let index = y * width as usize + x;
+        let fine = if weights[index] > 0.0 { sum[index] / weights[index] } else { coarse };
+        let confidence = (2.0 * coarse - 1.0).abs();
+        confidence * coarse + (1.0 - confidence) * fine
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+    use std::path::Path;
+    use crate::inference::{BackgroundRemover, OnnxOptions};
+    use crate::models::ModelOptions;
+
+    /// Largest mean difference from the golden mask, as a fraction of full scale.
+    const TOLERANCE: f64 = 0.01;
+
+    /// Compares the u2net mask of every image in tests/fixtures/golden with its `<name>.mask.png`.
+    /// Set UPDATE_GOLDEN=1 to (re)record the expected masks after an intended change.
+    #[test]
+    #[ignore = "needs the u2net weights; run with `cargo test -- --ignored`"]
+    fn u2net_masks_match_the_golden_files() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+        let mut remover = BackgroundRemover::from_options(&ModelOptions::default(), &OnnxOptions::default())
+            .expect("u2net model (see `image_converter models paths`)");
+        let mut inputs: Vec<_> = std::fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| !path.to_string_lossy().ends_with(".mask.png"))
+            .collect();
+        inputs.sort();
+        assert!(!inputs.is_empty(), "no fixtures in {}", dir.display());
+        for input in inputs {
+            let mask = predict_mask(&input.to_string_lossy(), &mut remover, &SegmentOptions::default()).unwrap();
+            let (height, width) = mask.dim();
+            let actual = GrayImage::from_fn(width as u32, height as u32, |x, y| {
+                Luma([(mask[[y as usize, x as usize]].clamp(0.0, 1.0) * 255.0).round() as u8])
+            });
+            let golden = input.with_extension("mask.png");
+            if update {
+                actual.save(&golden).unwrap();
+                continue;
+            }
+            let expected = image::open(&golden)
+                .unwrap_or_else(|_| panic!("missing {}; record it with UPDATE_GOLDEN=1", golden.display()))
+                .to_luma8();
+            assert_eq!(expected.dimensions(), actual.dimensions(), "{}", input.display());
+            let difference: u64 = expected.pixels().zip(actual.pixels())
+                .map(|(a, b)| a[0].abs_diff(b[0]) as u64)
+                .sum();
+            let mean = difference as f64 / (width * height * 255) as f64;
+            assert!(mean < TOLERANCE, "{}: mean difference {:.4} from the golden mask", input.display(), mean);
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use colored::*;
+use image::{DynamicImage, ImageFormat};
+use image::open;
+
+pub fn to_gif(input_path: &str, output_path: &str) -> Result<(), String> {
+    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Converting to GIF: {} -> {}", input_path, output_path));
+    let img = match open(input_path) {
+        Ok(i) => i,
+        Err(e) => {
+            return Err(format!("[ERROR] Failed to open input image '{}': {}", input_path, e));
+        }
+    };
+    match DynamicImage::ImageRgba8(img.to_rgba8()).save_with_format(output_path, ImageFormat::Gif) {
+        Ok(_) => {
+            println!("{} {} {}", "[SUCCESS]".bold().green(), "✔".green(), format!("Saved GIF: {}", output_path));
+            Ok(())
+        },
+        Err(e) => Err(format!("{} {} {}", "[ERROR]".bold().red(), "✖".red(), format!("Failed to save as GIF '{}': {}", output_path, e))),
+    }
+}
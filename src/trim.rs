@@ -0,0 +1,56 @@
+use image::{DynamicImage, GenericImageView, Rgba};
+use colored::*;
+
+pub fn parse_percent(value: &str) -> Result<f32, String> {
+    let number = value.trim().trim_end_matches('%');
+    let percent: f32 = number.parse()
+        .map_err(|_| format!("'{}' is not a valid percentage (e.g. 5%)", value))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(format!("'{}' must be between 0% and 100%", value));
+    }
+    Ok(percent / 100.0)
+}
+
+fn matches_border(pixel: Rgba<u8>, reference: Rgba<u8>, tolerance: f32) -> bool {
+    if reference[3] == 0 {
+        return pixel[3] == 0;
+    }
+    pixel.0.iter()
+        .zip(reference.0.iter())
+        .all(|(&a, &b)| (a as f32 - b as f32).abs() <= tolerance)
+}
+
+pub fn trim_borders(img: &DynamicImage, fuzz: f32, padding: u32) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return img.clone();
+    }
+    let rgba = img.to_rgba8();
+    let reference = *rgba.get_pixel(0, 0);
+    let tolerance = fuzz * 255.0;
+
+    let mut min_x = width;
+    let mut min_y = height;
+    let mut max_x = 0;
+    let mut max_y = 0;
+    for (x, y, pixel) in rgba.enumerate_pixels() {
+        if !matches_border(*pixel, reference, tolerance) {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+    if min_x > max_x || min_y > max_y {
+        println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), "Image is a single uniform color, nothing to trim.");
+        return img.clone();
+    }
+
+    let left = min_x.saturating_sub(padding);
+    let top = min_y.saturating_sub(padding);
+    let right = max_x.saturating_add(padding).min(width - 1);
+    let bottom = max_y.saturating_add(padding).min(height - 1);
+    let (new_width, new_height) = (right - left + 1, bottom - top + 1);
+    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Trimmed borders: {}x{} -> {}x{} (offset {},{})", width, height, new_width, new_height, left, top));
+    img.crop_imm(left, top, new_width, new_height)
+}
@@ -50,6 +50,70 @@ pub struct BatchOptions {
     pub format_index: usize,
     pub remove_bg: bool,
     pub strip_metadata: bool,
+    pub ops: ImageOps,
+}
+
+pub fn get_overlay_options(ops: &mut ImageOps) {
+    let add_overlay = Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("Add a watermark or text overlay?")
+        .default(false)
+        .interact()
+        .unwrap_or(false);
+    if !add_overlay {
+        return;
+    }
+    let positions: Vec<&str> = Anchor::ALL.iter().map(|a| a.label()).collect();
+    let watermark: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Watermark PNG path (leave empty for none)")
+        .allow_empty(true)
+        .interact_text()
+        .unwrap_or_default();
+    if !watermark.trim().is_empty() {
+        ops.watermark = Some(watermark.trim().to_string());
+        let position = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Watermark position")
+            .items(&positions)
+            .default(3)
+            .interact()
+            .unwrap_or(3);
+        ops.watermark_position = Anchor::ALL[position];
+        ops.watermark_opacity = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Watermark opacity (0-1)")
+            .default(ops.watermark_opacity)
+            .validate_with(|input: &f32| {
+                if (0.0..=1.0).contains(input) { Ok(()) } else { Err("Opacity must be between 0 and 1") }
+            })
+            .interact_text()
+            .unwrap_or(ops.watermark_opacity);
+        ops.watermark_tile = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Tile the watermark across the image?")
+            .default(false)
+            .interact()
+            .unwrap_or(false);
+    }
+    let text: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Overlay text (leave empty for none)")
+        .allow_empty(true)
+        .interact_text()
+        .unwrap_or_default();
+    if !text.trim().is_empty() {
+        ops.text = Some(text);
+        let position = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Text position")
+            .items(&positions)
+            .default(2)
+            .interact()
+            .unwrap_or(2);
+        ops.text_position = Anchor::ALL[position];
+        let font: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("TTF font path (leave empty for the bundled font)")
+            .allow_empty(true)
+            .interact_text()
+            .unwrap_or_default();
+        if !font.trim().is_empty() {
+            ops.font = Some(font.trim().to_string());
+        }
+    }
 }
 
 pub fn get_batch_options() -> Option<BatchOptions> {
@@ -95,6 +159,8 @@ pub fn get_batch_options() -> Option<BatchOptions> {
         .default(false)
         .interact()
         .unwrap_or(false);
+    let mut ops = ImageOps::default();
+    get_overlay_options(&mut ops);
     println!("\n{}", cyan.apply_to("Batch Summary:"));
     println!("  Input dir:    {}", input_dir);
     println!("  Output dir:   {}", output_dir);
@@ -103,6 +169,7 @@ pub fn get_batch_options() -> Option<BatchOptions> {
         println!("  Remove BG:    {}", if remove_bg { "Yes" } else { "No" });
     }
     println!("  Strip metadata: {}", if strip_metadata { "Yes" } else { "No" });
+    print_overlay_summary(&ops);
     let proceed = Confirm::with_theme(&ColorfulTheme::default())
         .with_prompt("Proceed with batch processing?")
         .default(true)
@@ -118,8 +185,18 @@ pub fn get_batch_options() -> Option<BatchOptions> {
         format_index,
         remove_bg,
         strip_metadata,
+        ops,
     })
 }
+
+fn print_overlay_summary(ops: &ImageOps) {
+    if let Some(watermark) = &ops.watermark {
+        println!("  Watermark:    {} ({})", watermark, if ops.watermark_tile { "tiled".to_string() } else { ops.watermark_position.label().to_lowercase() });
+    }
+    if let Some(text) = &ops.text {
+        println!("  Text:         \"{}\" ({})", text, ops.text_position.label().to_lowercase());
+    }
+}
 pub enum TuiAction {
     SingleFile,
     Batch,
@@ -153,11 +230,11 @@ use dialoguer::{Input, Select, theme::ColorfulTheme, Confirm};
 use dialoguer::console::Style;
 use std::path::{Path, PathBuf};
 use std::fs;
-use image::io::Reader as ImageReader;
-use colored::*;
 use crate::palette_extract;
+use crate::pipeline::ImageOps;
+use crate::watermark::Anchor;
 
-pub fn get_user_input() -> (String, String, usize, bool, bool) {
+pub fn get_user_input() -> (String, String, usize, bool, bool, ImageOps) {
     let cyan = Style::new().cyan().bold();
     println!("{}", cyan.apply_to("\n=== Image Converter TUI ===\n"));
 
@@ -193,11 +270,9 @@ pub fn get_user_input() -> (String, String, usize, bool, bool) {
                 if path.is_dir() {
                     items.push(format!("\u{1F4C1} {}", path.file_name()?.to_string_lossy()));
                     actions.push(Item::Dir(path));
-                } else if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                    if ["jpg", "jpeg", "png", "webp"].contains(&ext.to_lowercase().as_str()) {
-                        items.push(path.file_name()?.to_string_lossy().to_string());
-                        actions.push(Item::File(path));
-                    }
+                } else if let Some(ext) = path.extension().and_then(|e| e.to_str()) && ["jpg", "jpeg", "png", "webp"].contains(&ext.to_lowercase().as_str()) {
+                    items.push(path.file_name()?.to_string_lossy().to_string());
+                    actions.push(Item::File(path));
                 }
             }
             let selection = Select::with_theme(&ColorfulTheme::default())
@@ -240,31 +315,30 @@ pub fn get_user_input() -> (String, String, usize, bool, bool) {
         .interact()
         .unwrap_or(false);
     if show_palette {
+        let colors: usize = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("How many colors?")
+            .default(6)
+            .validate_with(|n: &usize| if (1..=palette_extract::MAX_COLORS).contains(n) { Ok(()) } else { Err(format!("Pick between 1 and {} colors", palette_extract::MAX_COLORS)) })
+            .interact_text()
+            .unwrap_or(6);
+        let contrast = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Suggest readable text/background pairs (WCAG contrast)?")
+            .default(false)
+            .interact()
+            .unwrap_or(false);
         println!("\n{}", Style::new().cyan().apply_to("Extracting color palette..."));
-        let img = match ImageReader::open(&input_path) {
-            Ok(reader) => match reader.decode() {
-                Ok(img) => img,
-                Err(e) => {
-                    eprintln!("[ERROR] Failed to decode image for palette extraction: {}", e);
-                    std::process::exit(1);
+        match palette_extract::palette_from_file(&input_path, &palette_extract::PaletteOptions { colors, ..Default::default() }) {
+            Ok(palette) => {
+                palette_extract::print_palette(&palette);
+                if contrast {
+                    palette_extract::print_contrast_report(&palette, true);
                 }
-            },
+            }
             Err(e) => {
-                eprintln!("[ERROR] Failed to open image for palette extraction: {}", e);
+                eprintln!("[ERROR] {}", e);
                 std::process::exit(1);
             }
-        };
-        let palette = palette_extract::extract_palette(&img, 6);
-        println!("\n{}", Style::new().magenta().bold().apply_to("Dominant Color Palette:"));
-        for hex in &palette {
-            print!("{}  ", "  ".on_truecolor(
-                u8::from_str_radix(&hex[1..3], 16).unwrap_or(0),
-                u8::from_str_radix(&hex[3..5], 16).unwrap_or(0),
-                u8::from_str_radix(&hex[5..7], 16).unwrap_or(0)
-            ));
-            print!("{}  ", hex.bold());
         }
-        println!("\n");
     }
 
     let output_base = Input::with_theme(&ColorfulTheme::default())
@@ -315,6 +389,9 @@ pub fn get_user_input() -> (String, String, usize, bool, bool) {
         .interact()
         .unwrap_or(false);
 
+    let mut ops = ImageOps::default();
+    get_overlay_options(&mut ops);
+
     println!("\n{}", cyan.apply_to("Summary:"));
     println!("  Input file:   {}", input_path);
     println!("  Output name:  {}", output_base);
@@ -323,6 +400,7 @@ pub fn get_user_input() -> (String, String, usize, bool, bool) {
         println!("  Remove BG:    {}", if remove_bg { "Yes" } else { "No" });
     }
     println!("  Strip metadata: {}", if strip_metadata { "Yes" } else { "No" });
+    print_overlay_summary(&ops);
 
     let proceed = Confirm::with_theme(&ColorfulTheme::default())
         .with_prompt("Proceed with these settings?")
@@ -336,7 +414,7 @@ pub fn get_user_input() -> (String, String, usize, bool, bool) {
         println!("{}", Style::new().red().apply_to("Operation cancelled by user."));
         std::process::exit(0);
     }
-    (input_path, output_base, format_index, remove_bg, strip_metadata)
+    (input_path, output_base, format_index, remove_bg, strip_metadata, ops)
 }
 
 pub fn show_about_help() {
@@ -354,6 +432,7 @@ pub fn show_about_help() {
     println!("  - Batch processing and single file mode");
     println!("  - Color palette extraction");
     println!("  - Metadata stripping");
+    println!("  - Border trimming, watermark and text overlays");
     println!("  - TUI with file/directory explorer");
     println!("  - Robust error handling and colored logs\n");
     println!("Usage:");
@@ -0,0 +1,146 @@
+use image::{imageops, Rgba, RgbaImage};
+use rusttype::{point, Font, Scale};
+use colored::*;
+use std::fs;
+
+const BUNDLED_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+
+#[derive(Copy, Clone, PartialEq, Eq, clap::ValueEnum, Debug)]
+#[clap(rename_all = "kebab-case")]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+impl Anchor {
+    pub const ALL: [Anchor; 5] = [Anchor::TopLeft, Anchor::TopRight, Anchor::BottomLeft, Anchor::BottomRight, Anchor::Center];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Anchor::TopLeft => "Top left",
+            Anchor::TopRight => "Top right",
+            Anchor::BottomLeft => "Bottom left",
+            Anchor::BottomRight => "Bottom right",
+            Anchor::Center => "Center",
+        }
+    }
+
+    fn place(&self, canvas: (u32, u32), item: (u32, u32), margin: u32) -> (i64, i64) {
+        let (cw, ch) = (canvas.0 as i64, canvas.1 as i64);
+        let (iw, ih) = (item.0 as i64, item.1 as i64);
+        let m = margin as i64;
+        match self {
+            Anchor::TopLeft => (m, m),
+            Anchor::TopRight => (cw - iw - m, m),
+            Anchor::BottomLeft => (m, ch - ih - m),
+            Anchor::BottomRight => (cw - iw - m, ch - ih - m),
+            Anchor::Center => ((cw - iw) / 2, (ch - ih) / 2),
+        }
+    }
+}
+
+pub fn parse_scale(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(scale) if scale > 0.0 && scale <= 1.0 => Ok(scale),
+        _ => Err(format!("'{}' is not a valid watermark scale (must be above 0 and at most 1)", value)),
+    }
+}
+
+pub fn parse_text_size(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(size) if size.is_finite() && size > 0.0 => Ok(size),
+        _ => Err(format!("'{}' is not a valid text size (must be a number of pixels above 0)", value)),
+    }
+}
+
+pub fn parse_opacity(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(opacity) if (0.0..=1.0).contains(&opacity) => Ok(opacity),
+        _ => Err(format!("'{}' is not a valid opacity (must be between 0 and 1)", value)),
+    }
+}
+
+fn fade(layer: &mut RgbaImage, opacity: f32) {
+    let opacity = opacity.clamp(0.0, 1.0);
+    for pixel in layer.pixels_mut() {
+        pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+    }
+}
+
+pub fn apply_watermark(img: &mut RgbaImage, watermark_path: &str, anchor: Anchor, scale: f32, margin: u32, opacity: f32, tile: bool) -> Result<(), String> {
+    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Compositing watermark: {}", watermark_path));
+    let mark = image::open(watermark_path)
+        .map_err(|e| format!("[ERROR] Failed to open watermark '{}': {}", watermark_path, e))?;
+    let target_width = ((img.width() as f32 * scale).round() as u32).max(1);
+    let target_height = ((mark.height() as f32 * target_width as f32 / mark.width().max(1) as f32).round() as u32).max(1);
+    let mut mark = mark.resize_exact(target_width, target_height, imageops::FilterType::Lanczos3).to_rgba8();
+    fade(&mut mark, opacity);
+
+    if tile {
+        let step_x = target_width as i64 + margin as i64;
+        let step_y = target_height as i64 + margin as i64;
+        let mut y = margin as i64;
+        while y < img.height() as i64 {
+            let mut x = margin as i64;
+            while x < img.width() as i64 {
+                imageops::overlay(img, &mark, x, y);
+                x += step_x;
+            }
+            y += step_y;
+        }
+    } else {
+        let (x, y) = anchor.place(img.dimensions(), mark.dimensions(), margin);
+        imageops::overlay(img, &mark, x, y);
+    }
+    Ok(())
+}
+
+fn render_text(font: &Font, text: &str, size: f32, color: Rgba<u8>) -> RgbaImage {
+    let scale = Scale::uniform(size);
+    let v_metrics = font.v_metrics(scale);
+    let glyphs: Vec<_> = font.layout(text, scale, point(0.0, v_metrics.ascent)).collect();
+    let width = glyphs.iter()
+        .filter_map(|g| g.pixel_bounding_box())
+        .map(|bb| bb.max.x)
+        .max()
+        .unwrap_or(0)
+        .max(1) as u32;
+    let height = ((v_metrics.ascent - v_metrics.descent).ceil() as u32).max(1);
+
+    let mut layer = RgbaImage::from_pixel(width, height, Rgba([color[0], color[1], color[2], 0]));
+    for glyph in &glyphs {
+        if let Some(bb) = glyph.pixel_bounding_box() {
+            glyph.draw(|gx, gy, coverage| {
+                let x = gx as i32 + bb.min.x;
+                let y = gy as i32 + bb.min.y;
+                if x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height {
+                    let alpha = (coverage * color[3] as f32).round() as u8;
+                    let pixel = layer.get_pixel_mut(x as u32, y as u32);
+                    pixel[3] = pixel[3].max(alpha);
+                }
+            });
+        }
+    }
+    layer
+}
+
+pub fn draw_text(img: &mut RgbaImage, text: &str, font_path: Option<&str>, size: f32, color: Rgba<u8>, anchor: Anchor, margin: u32) -> Result<(), String> {
+    println!("{} {} {}", "[INFO]".bold().yellow(), "ℹ".bold().blue(), format!("Drawing text overlay: \"{}\"", text));
+    let font = match font_path {
+        Some(path) => {
+            let data = fs::read(path)
+                .map_err(|e| format!("[ERROR] Failed to read font '{}': {}", path, e))?;
+            Font::try_from_vec(data)
+                .ok_or_else(|| format!("[ERROR] '{}' is not a valid TTF/OTF font", path))?
+        }
+        None => Font::try_from_bytes(BUNDLED_FONT)
+            .ok_or_else(|| "[ERROR] Failed to load the bundled font".to_string())?,
+    };
+    let layer = render_text(&font, text, size, color);
+    let (x, y) = anchor.place(img.dimensions(), layer.dimensions(), margin);
+    imageops::overlay(img, &layer, x, y);
+    Ok(())
+}